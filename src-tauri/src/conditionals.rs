@@ -0,0 +1,281 @@
+use crate::diagnostics::{Diagnostic, DiagnosticCode, DiagnosticSource, IncludeFrame, Severity};
+use crate::hosts_document::{HostsDocument, HostsLine, LineKind};
+
+/// Facts about the machine that `@if(...)` conditions are checked against
+pub struct Platform {
+    /// As returned by `tauri_plugin_os::platform()`, e.g. `linux` or `macos`
+    pub os: String,
+    pub hostname: String,
+}
+
+impl Platform {
+    pub fn current() -> Self {
+        Platform {
+            os: tauri_plugin_os::platform().to_string(),
+            hostname: tauri_plugin_os::hostname(),
+        }
+    }
+}
+
+/// What a condition looks at
+enum Subject<'a> {
+    Os,
+    Hostname,
+    Env(&'a str),
+}
+
+/// Parameter of `@if(...)`: `os=linux`, `hostname!=work-laptop`, `env.NAME` or `env.NAME=value`
+pub struct Condition<'a> {
+    subject: Subject<'a>,
+    /// `None` when only checking that an environment variable is set
+    expected: Option<&'a str>,
+    negated: bool,
+}
+
+impl<'a> Condition<'a> {
+    pub const SYNTAX: &'static str = "os=NAME, hostname=NAME, env.NAME or env.NAME=value";
+
+    pub fn parse(param: &'a str) -> Option<Self> {
+        let (key, expected, negated) = match param.split_once("!=") {
+            Some((key, value)) => (key, Some(value), true),
+            None => match param.split_once('=') {
+                Some((key, value)) => (key, Some(value), false),
+                None => (param, None, false),
+            },
+        };
+        let key = key.trim();
+        let expected = expected.map(str::trim);
+
+        let subject = match key {
+            "os" => Subject::Os,
+            "hostname" => Subject::Hostname,
+            _ => match key.strip_prefix("env.") {
+                Some(name) if !name.is_empty() && !name.contains(char::is_whitespace) => {
+                    Subject::Env(name)
+                }
+                _ => return None,
+            },
+        };
+        if expected == Some("") || (expected.is_none() && !matches!(subject, Subject::Env(_))) {
+            return None;
+        }
+
+        Some(Condition {
+            subject,
+            expected,
+            negated,
+        })
+    }
+
+    pub fn matches(&self, platform: &Platform) -> bool {
+        let result = match (&self.subject, self.expected) {
+            (Subject::Os, Some(expected)) => platform.os.eq_ignore_ascii_case(expected),
+            (Subject::Hostname, Some(expected)) => platform.hostname.eq_ignore_ascii_case(expected),
+            (Subject::Env(name), Some(expected)) => {
+                std::env::var(name).is_ok_and(|v| v == expected)
+            }
+            (Subject::Env(name), None) => std::env::var(name).is_ok_and(|v| !v.is_empty()),
+            (_, None) => false,
+        };
+        result != self.negated
+    }
+}
+
+/// Content of a file with the lines of inactive `@if` branches commented out
+pub struct Evaluation {
+    pub content: String,
+    /// Unterminated and unmatched blocks
+    pub diagnostics: Vec<Diagnostic>,
+    /// Numbers of the lines commented out, in ascending order
+    pub inactive_lines: Vec<usize>,
+}
+
+/// An `@if` block that has not been closed yet
+struct Block<'a, 'd> {
+    line: &'d HostsLine<'a>,
+    /// Whether the enclosing block is active
+    parent_active: bool,
+    matched: bool,
+    /// Whether the lines following it are written
+    active: bool,
+    in_else: bool,
+}
+
+/// Evaluates `@if(condition)` / `@else` / `@endif` blocks in `content` against `platform`.
+/// Line numbers are kept intact: the control lines stay in place and lines of inactive
+/// branches become `# Inactive: ...` comments.
+pub fn evaluate(
+    content: &str,
+    platform: &Platform,
+    source: &DiagnosticSource,
+    included_from: &[IncludeFrame],
+) -> Evaluation {
+    let document = HostsDocument::parse(content);
+    let mut stack: Vec<Block> = Vec::new();
+    let mut diagnostics = Vec::new();
+    let mut inactive_lines = Vec::new();
+    let mut result = String::with_capacity(content.len());
+
+    let mut report = |line: &HostsLine<'_>, message: String, code| {
+        let mut diagnostic = Diagnostic::new(
+            Severity::Error,
+            code,
+            message,
+            source,
+            line.raw,
+            line.content_span(),
+        );
+        diagnostic.included_from = included_from.to_vec();
+        diagnostics.push(diagnostic);
+    };
+
+    for (index, line) in document.lines.iter().enumerate() {
+        if index > 0 {
+            result.push('\n');
+        }
+
+        let active = stack.last().is_none_or(|block| block.active);
+        let control = match &line.kind {
+            LineKind::Directive(directive) => Some(directive),
+            _ => None,
+        }
+        .filter(|directive| matches!(directive.name.text, "if" | "else" | "endif"));
+
+        let Some(directive) = control else {
+            match &line.kind {
+                LineKind::Blank | LineKind::Comment(_) => result.push_str(line.raw),
+                _ if active => result.push_str(line.raw),
+                _ => {
+                    result.push_str("# Inactive: ");
+                    result.push_str(line.raw.trim());
+                    inactive_lines.push(line.number);
+                }
+            }
+            continue;
+        };
+        result.push_str(line.raw);
+
+        match directive.name.text {
+            "if" => {
+                // Invalid conditions are reported by the validator
+                let matched = Condition::parse(directive.param.text)
+                    .is_some_and(|condition| condition.matches(platform));
+                stack.push(Block {
+                    line,
+                    parent_active: active,
+                    matched,
+                    active: active && matched,
+                    in_else: false,
+                });
+            }
+            "else" => match stack.last_mut() {
+                Some(block) if !block.in_else => {
+                    block.in_else = true;
+                    block.active = block.parent_active && !block.matched;
+                }
+                Some(block) => report(
+                    line,
+                    format!("Duplicate @else for the @if at line {}", block.line.number),
+                    DiagnosticCode::UnmatchedBlock,
+                ),
+                None => report(
+                    line,
+                    "@else without a matching @if".to_string(),
+                    DiagnosticCode::UnmatchedBlock,
+                ),
+            },
+            _ => {
+                if stack.pop().is_none() {
+                    report(
+                        line,
+                        "@endif without a matching @if".to_string(),
+                        DiagnosticCode::UnmatchedBlock,
+                    );
+                }
+            }
+        }
+    }
+
+    for block in stack {
+        report(
+            block.line,
+            "Missing @endif for this @if".to_string(),
+            DiagnosticCode::UnterminatedBlock,
+        );
+    }
+
+    Evaluation {
+        content: result,
+        diagnostics,
+        inactive_lines,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn platform() -> Platform {
+        Platform {
+            os: "linux".to_string(),
+            hostname: "work-laptop".to_string(),
+        }
+    }
+
+    fn matches(param: &str) -> Option<bool> {
+        Condition::parse(param).map(|condition| condition.matches(&platform()))
+    }
+
+    #[test]
+    fn parses_conditions() {
+        assert_eq!(matches("os=linux"), Some(true));
+        assert_eq!(matches(" os = Linux "), Some(true));
+        assert_eq!(matches("os!=linux"), Some(false));
+        assert_eq!(matches("hostname=WORK-LAPTOP"), Some(true));
+        assert_eq!(matches("hostname!=home"), Some(true));
+        assert_eq!(matches("env.HEDIT_TEST_UNSET_VARIABLE"), Some(false));
+        assert_eq!(matches("env.HEDIT_TEST_UNSET_VARIABLE!=x"), Some(true));
+    }
+
+    #[test]
+    fn rejects_invalid_conditions() {
+        for param in ["os", "os=", "user=me", "env.", "env.A B=1", "hostname"] {
+            assert!(Condition::parse(param).is_none(), "{}", param);
+        }
+    }
+
+    #[test]
+    fn comments_out_inactive_branches() {
+        let content = "@if(os=linux)\n10.0.0.1 a\n@else\n10.0.0.2 a\n@endif\n10.0.0.3 b";
+        let evaluation = evaluate(content, &platform(), &DiagnosticSource::Inline, &[]);
+        assert_eq!(
+            evaluation.content,
+            "@if(os=linux)\n10.0.0.1 a\n@else\n# Inactive: 10.0.0.2 a\n@endif\n10.0.0.3 b"
+        );
+        assert_eq!(evaluation.inactive_lines, [4]);
+        assert!(evaluation.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn reports_unbalanced_blocks() {
+        let evaluation = evaluate(
+            "@if(os=macos)\n@else\n@else\n@endif\n@endif\n@if(os=linux)",
+            &platform(),
+            &DiagnosticSource::Inline,
+            &[],
+        );
+        let codes: Vec<(usize, DiagnosticCode)> = evaluation
+            .diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.line, diagnostic.code))
+            .collect();
+        assert_eq!(
+            codes,
+            [
+                (3, DiagnosticCode::UnmatchedBlock),
+                (5, DiagnosticCode::UnmatchedBlock),
+                (6, DiagnosticCode::UnterminatedBlock),
+            ]
+        );
+    }
+}
@@ -0,0 +1,139 @@
+use serde::Serialize;
+use std::fmt;
+
+use crate::hosts_document::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// Stable identifier of a validation problem, serialized as kebab-case (e.g. `invalid-ip`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DiagnosticCode {
+    InvalidDirective,
+    UnsupportedDirective,
+    InvalidParameter,
+    InvalidUrl,
+    InsecureUrl,
+    LocalFileNotFound,
+    RemoteFetchFailed,
+    StaleRemoteCache,
+    UnconvertedLines,
+    IncludeCycle,
+    IncludeDepthExceeded,
+    InvalidIp,
+    UnscopedZone,
+    UnknownInterface,
+    EmbeddedIpv4,
+    InvalidHostname,
+    MixedScriptHostname,
+    MissingHostname,
+    DuplicateHostname,
+    UndefinedVariable,
+    UnusedVariable,
+    UnterminatedBlock,
+    UnmatchedBlock,
+}
+
+/// Where the validated content came from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", content = "name", rename_all = "lowercase")]
+pub enum DiagnosticSource {
+    Inline,
+    Local(String),
+    Remote(String),
+}
+
+impl DiagnosticSource {
+    fn describe_line(&self, line: usize) -> String {
+        match self {
+            DiagnosticSource::Inline => format!("line {}", line),
+            DiagnosticSource::Local(name) => {
+                format!("line {} of local hosts file '{}'", line, name)
+            }
+            DiagnosticSource::Remote(url) => {
+                format!("line {} of remote hosts file '{}'", line, url)
+            }
+        }
+    }
+}
+
+/// Include directive that pulled a file into a composition
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct IncludeFrame {
+    pub source: DiagnosticSource,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Diagnostic {
+    /// 1-based line number
+    pub line: usize,
+    /// 1-based column of the first character
+    pub start_column: usize,
+    /// 1-based column after the last character
+    pub end_column: usize,
+    pub severity: Severity,
+    pub code: DiagnosticCode,
+    pub message: String,
+    pub source: DiagnosticSource,
+    /// Include directives leading to `source`, outermost first
+    pub included_from: Vec<IncludeFrame>,
+}
+
+impl Diagnostic {
+    /// Creates a diagnostic for `span`, converting its byte offsets in `raw` to character columns
+    pub fn new(
+        severity: Severity,
+        code: DiagnosticCode,
+        message: String,
+        source: &DiagnosticSource,
+        raw: &str,
+        span: Span,
+    ) -> Self {
+        let column = |offset: usize| raw.get(..offset).map_or(offset, |s| s.chars().count()) + 1;
+
+        Diagnostic {
+            line: span.line,
+            start_column: column(span.start),
+            end_column: column(span.end),
+            severity,
+            code,
+            message,
+            source: source.clone(),
+            included_from: Vec::new(),
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at {}",
+            self.message,
+            self.source.describe_line(self.line)
+        )?;
+        for frame in self.included_from.iter().rev() {
+            write!(
+                f,
+                ", included from {}",
+                frame.source.describe_line(frame.line)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+pub fn first_error(diagnostics: &[Diagnostic]) -> Option<&Diagnostic> {
+    diagnostics.iter().find(|d| d.is_error())
+}
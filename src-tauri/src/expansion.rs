@@ -0,0 +1,212 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+static RANGE_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"\{(\d+)\.\.(\d+)\}").unwrap());
+
+/// Maximum number of lines a single `@expand` may generate
+pub const MAX_EXPANSION: u64 = 1024;
+
+/// `{start..end}`, counting down when `end` is lower than `start`
+struct Range {
+    start: u64,
+    end: u64,
+    /// Values are zero-padded to this width when a bound has a leading zero, as in `{01..40}`
+    width: usize,
+}
+
+impl Range {
+    /// Wider than the bounds, `{0..18446744073709551615}` has one value more than `u64` holds
+    fn len(&self) -> u128 {
+        u128::from(self.start.abs_diff(self.end)) + 1
+    }
+
+    fn value(&self, index: u64) -> String {
+        let value = if self.start <= self.end {
+            self.start + index
+        } else {
+            self.start - index
+        };
+        format!("{:0width$}", value, width = self.width)
+    }
+}
+
+/// A word of an `@expand` parameter, with at most one range
+struct Template {
+    text: String,
+    /// The range and its byte offsets in `text`
+    range: Option<(Range, usize, usize)>,
+}
+
+impl Template {
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut ranges = RANGE_REGEX.captures_iter(text);
+        let Some(captures) = ranges.next() else {
+            return Ok(Template {
+                text: text.to_string(),
+                range: None,
+            });
+        };
+        if ranges.next().is_some() {
+            return Err(format!("Only one range is allowed in '{}'", text));
+        }
+
+        let whole = captures.get(0).unwrap();
+        let (start, end) = (&captures[1], &captures[2]);
+        let bound = |value: &str| {
+            value
+                .parse::<u64>()
+                .map_err(|_| format!("Range bound '{}' is too large", value))
+        };
+        let width = if (start.len() > 1 && start.starts_with('0'))
+            || (end.len() > 1 && end.starts_with('0'))
+        {
+            start.len().max(end.len())
+        } else {
+            0
+        };
+
+        Ok(Template {
+            text: text.to_string(),
+            range: Some((
+                Range {
+                    start: bound(start)?,
+                    end: bound(end)?,
+                    width,
+                },
+                whole.start(),
+                whole.end(),
+            )),
+        })
+    }
+
+    fn render(&self, index: u64) -> String {
+        match &self.range {
+            Some((range, start, end)) => format!(
+                "{}{}{}",
+                &self.text[..*start],
+                range.value(index),
+                &self.text[*end..]
+            ),
+            None => self.text.clone(),
+        }
+    }
+}
+
+/// Parameter of `@expand(10.0.0.{1..40} node{1..40}.cluster.local)`: an IP template followed by
+/// hostname templates. Every range must have the same number of values.
+pub struct Expansion {
+    templates: Vec<Template>,
+    len: u64,
+}
+
+impl Expansion {
+    pub fn parse(param: &str) -> Result<Self, String> {
+        let templates = param
+            .split_whitespace()
+            .map(Template::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        if templates.len() < 2 {
+            return Err(format!(
+                "Invalid expansion '{}'. Expected an IP followed by one or more hostnames",
+                param
+            ));
+        }
+
+        let mut ranges = templates
+            .iter()
+            .filter_map(|template| template.range.as_ref().map(|(range, ..)| (template, range)));
+        let Some((first, first_range)) = ranges.next() else {
+            return Err(format!("No {{start..end}} range in expansion '{}'", param));
+        };
+        let len = first_range.len();
+        if let Some((other, other_range)) = ranges.find(|(_, range)| range.len() != len) {
+            return Err(format!(
+                "Ranges have different lengths: '{}' has {} values, '{}' has {}",
+                first.text,
+                len,
+                other.text,
+                other_range.len()
+            ));
+        }
+        if len > u128::from(MAX_EXPANSION) {
+            return Err(format!(
+                "Expansion of {} lines exceeds the limit of {}",
+                len, MAX_EXPANSION
+            ));
+        }
+
+        Ok(Expansion {
+            templates,
+            len: len as u64,
+        })
+    }
+
+    /// The generated `IP hostname...` lines
+    pub fn lines(&self) -> impl Iterator<Item = String> + '_ {
+        (0..self.len).map(|index| {
+            self.templates
+                .iter()
+                .map(|template| template.render(index))
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(param: &str) -> Result<Vec<String>, String> {
+        Expansion::parse(param).map(|expansion| expansion.lines().collect())
+    }
+
+    #[test]
+    fn expands_ranges() {
+        assert_eq!(
+            expand("10.0.0.{1..3} node{1..3}.test"),
+            Ok(vec![
+                "10.0.0.1 node1.test".to_string(),
+                "10.0.0.2 node2.test".to_string(),
+                "10.0.0.3 node3.test".to_string(),
+            ])
+        );
+        assert_eq!(
+            expand("10.0.0.1 web{09..11}.test"),
+            Ok(vec![
+                "10.0.0.1 web09.test".to_string(),
+                "10.0.0.1 web10.test".to_string(),
+                "10.0.0.1 web11.test".to_string(),
+            ])
+        );
+        assert_eq!(
+            expand("10.0.0.{2..1} n{2..1}"),
+            Ok(vec!["10.0.0.2 n2".to_string(), "10.0.0.1 n1".to_string()])
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_expansions() {
+        assert!(expand("10.0.0.1").is_err());
+        assert!(expand("10.0.0.1 a.test").is_err());
+        assert!(expand("10.0.0.{1..2} n{1..3}").is_err());
+        assert!(expand("10.0.0.1 n{1..2}-{1..2}").is_err());
+        assert!(expand("10.0.0.1 n{1..99999999999999999999}").is_err());
+    }
+
+    #[test]
+    fn limits_the_number_of_lines() {
+        assert_eq!(
+            expand("10.0.0.1 n{1..1024}").map(|lines| lines.len()),
+            Ok(1024)
+        );
+        assert!(expand("10.0.0.1 n{1..1025}").is_err());
+        assert_eq!(
+            expand("10.0.0.1 n{0..18446744073709551615}").err(),
+            Some(format!(
+                "Expansion of 18446744073709551616 lines exceeds the limit of {}",
+                MAX_EXPANSION
+            ))
+        );
+    }
+}
@@ -0,0 +1,211 @@
+use serde::{Deserialize, Serialize};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use tauri::{command, Manager};
+use tokio::fs;
+
+use crate::hosts_document::{HostsLine, LineKind};
+use crate::hosts_parser::{self, is_ignored_host};
+use crate::temp_file::{LineReader, TempFile};
+
+/// Windows only reads the first 9 hostnames of an entry
+const WINDOWS_MAX_HOSTNAMES: usize = 9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExportFormat {
+    /// `host-record=name,address` lines for dnsmasq
+    Dnsmasq,
+    /// `local-data:` records in a `server:` clause for unbound
+    Unbound,
+    /// A `hosts` block for a CoreDNS Corefile
+    Coredns,
+    /// Hosts syntax with CRLF line endings and at most 9 hostnames per entry
+    Windows,
+    /// `[{"ip": ..., "hostnames": [...], "comment": ...}]`, the comment without its `#`
+    Json,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonEntry<'a> {
+    ip: &'a str,
+    hostnames: Vec<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    comment: Option<&'a str>,
+}
+
+/// Writes the entries of a composed hosts file in another format.
+/// `localhost` and the other names every system defines are left out of DNS server formats.
+struct Exporter<W: Write> {
+    format: ExportFormat,
+    writer: W,
+    entries: usize,
+}
+
+impl<W: Write> Exporter<W> {
+    fn new(format: ExportFormat, writer: W) -> Self {
+        Exporter {
+            format,
+            writer,
+            entries: 0,
+        }
+    }
+
+    fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        match self.format {
+            ExportFormat::Windows => write!(self.writer, "{}\r\n", line),
+            _ => writeln!(self.writer, "{}", line),
+        }
+    }
+
+    fn begin(&mut self, header: &str) -> std::io::Result<()> {
+        match self.format {
+            ExportFormat::Json => self.writer.write_all(b"["),
+            ExportFormat::Unbound => {
+                self.write_line(header)?;
+                self.write_line("server:")
+            }
+            ExportFormat::Coredns => {
+                self.write_line(header)?;
+                self.write_line("hosts {")
+            }
+            ExportFormat::Dnsmasq | ExportFormat::Windows => self.write_line(header),
+        }
+    }
+
+    fn line(&mut self, line: &HostsLine<'_>) -> std::io::Result<()> {
+        let LineKind::Entry(entry) = &line.kind else {
+            // Comments are only kept where the layout of the file is
+            if self.format == ExportFormat::Windows {
+                self.write_line(line.raw.trim_end())?;
+            }
+            return Ok(());
+        };
+
+        let comment = entry.comment.map(|comment| comment.text.trim_end());
+        let all: Vec<&str> = entry.hostnames.iter().map(|host| host.text).collect();
+        let hostnames: Vec<&str> = all
+            .iter()
+            .copied()
+            .filter(|host| !is_ignored_host(host))
+            .collect();
+        // DNS servers cannot use the zone of a scoped address
+        let address = entry.ip.text.split('%').next().unwrap_or_default();
+
+        match self.format {
+            ExportFormat::Windows => {
+                for chunk in all.chunks(WINDOWS_MAX_HOSTNAMES) {
+                    let mut text = format!("{} {}", entry.ip.text, chunk.join(" "));
+                    if let Some(comment) = comment {
+                        text.push(' ');
+                        text.push_str(comment);
+                    }
+                    self.write_line(&text)?;
+                }
+            }
+            ExportFormat::Json => {
+                let separator: &[u8] = if self.entries == 0 { b"\n" } else { b",\n" };
+                self.writer.write_all(separator)?;
+                let json = JsonEntry {
+                    ip: entry.ip.text,
+                    hostnames: all,
+                    comment: comment.map(|comment| comment.trim_start_matches('#').trim()),
+                };
+                serde_json::to_writer(&mut self.writer, &json)?;
+            }
+            _ if hostnames.is_empty() => return Ok(()),
+            ExportFormat::Dnsmasq => {
+                let line = format!("host-record={},{}", hostnames.join(","), address);
+                self.write_line(&line)?;
+            }
+            ExportFormat::Unbound => {
+                let record_type = if address.contains(':') { "AAAA" } else { "A" };
+                for host in hostnames {
+                    let line = format!(
+                        "    local-data: \"{}. IN {} {}\"",
+                        host, record_type, address
+                    );
+                    self.write_line(&line)?;
+                }
+            }
+            ExportFormat::Coredns => {
+                let line = format!("    {} {}", address, hostnames.join(" "));
+                self.write_line(&line)?;
+            }
+        }
+        self.entries += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        match self.format {
+            ExportFormat::Json => self.writer.write_all(b"\n]\n")?,
+            ExportFormat::Coredns => {
+                // Names not in the file are resolved by the next plugin
+                self.write_line("    fallthrough")?;
+                self.write_line("}")?;
+            }
+            _ => {}
+        }
+        self.writer.flush()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportSummary {
+    pub destination: String,
+    /// Number of entries written
+    pub entries: usize,
+}
+
+/// Composes the hosts file `file_id` and writes it to `destination` in `format`,
+/// so other DNS software sees the same mappings as this machine
+#[command]
+pub async fn export_hosts_file(
+    app_handle: tauri::AppHandle,
+    file_id: String,
+    format: ExportFormat,
+    destination: String,
+) -> Result<ExportSummary, String> {
+    let destination_path = Path::new(&destination);
+    if !destination_path.is_absolute() {
+        return Err(format!(
+            "Destination '{}' is not an absolute path",
+            destination
+        ));
+    }
+
+    let dir = app_handle.path().app_data_dir().unwrap();
+    let file_path = dir.join("files").join(format!("{}.hosts", file_id));
+    let content = fs::read_to_string(&file_path)
+        .await
+        .map_err(|e| format!("Error reading hosts file '{}': {}", file_id, e))?;
+
+    let (composed, file) = TempFile::create("hedit-export")?;
+    hosts_parser::parse_hosts_file(&app_handle, &content, file).await?;
+
+    let output = std::fs::File::create(destination_path)
+        .map_err(|e| format!("Error creating '{}': {}", destination, e))?;
+    let mut exporter = Exporter::new(format, BufWriter::new(output));
+    let write_error = |e: std::io::Error| format!("Error writing '{}': {}", destination, e);
+
+    exporter
+        .begin(&format!(
+            "# Exported from Hedit on {}",
+            chrono::Local::now().format("%Y-%m-%d %H:%M:%S")
+        ))
+        .map_err(write_error)?;
+    let mut reader = LineReader::open(composed.path())?;
+    while let Some((number, raw)) = reader.next_line()? {
+        exporter
+            .line(&HostsLine::parse(number, &raw))
+            .map_err(write_error)?;
+    }
+    exporter.finish().map_err(write_error)?;
+
+    Ok(ExportSummary {
+        destination,
+        entries: exporter.entries,
+    })
+}
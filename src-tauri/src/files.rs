@@ -1,17 +1,35 @@
+use crate::diagnostics::Diagnostic;
+use crate::formatter::{self, FormatOptions};
+use crate::hosts_backups;
 use crate::hosts_parser;
-use std::process::Command;
+use crate::hosts_watcher;
+use crate::managed_block;
+use crate::remote_cache;
+use crate::settings_store::{self, ConfigKey};
+use crate::temp_file::TempFile;
+use std::io::Write;
 use tauri::{command, Manager};
 use tokio::fs;
 
 use tauri::AppHandle;
 
+/// First line of every hosts file Hedit installs
+pub const GENERATED_HEADER: &str = "# This file was generated by the Hedit app";
+
+/// Saves `content`, formatted first when `format` is given, and returns what was written
 #[command]
 pub async fn write_file(
     app_handle: tauri::AppHandle,
     file_name: String,
     content: String,
     is_active: bool,
-) -> Result<(), String> {
+    format: Option<FormatOptions>,
+) -> Result<String, String> {
+    let content = match format {
+        Some(options) => formatter::format_hosts(&content, &options),
+        None => content,
+    };
+
     hosts_parser::validate_hosts_file(&app_handle, &content).await?;
 
     let dir = app_handle.path().app_data_dir().unwrap();
@@ -22,10 +40,31 @@ pub async fn write_file(
         .map_err(|e| e.to_string())?;
 
     if is_active {
-        write_system_hosts(&app_handle, content).await?;
+        write_system_hosts(&app_handle, content.clone()).await?;
     }
 
-    Ok(())
+    Ok(content)
+}
+
+/// Formats `content` canonically, see `formatter::format_hosts`
+#[command]
+pub fn format_hosts_file(content: String, options: Option<FormatOptions>) -> String {
+    formatter::format_hosts(&content, &options.unwrap_or_default())
+}
+
+/// Returns every validation problem in `content`.
+/// With `expand_includes`, included files are fetched and checked as well.
+#[command]
+pub async fn get_file_diagnostics(
+    app_handle: tauri::AppHandle,
+    content: String,
+    expand_includes: bool,
+) -> Result<Vec<Diagnostic>, String> {
+    if expand_includes {
+        hosts_parser::compose_hosts_file(&app_handle, &content, std::io::sink()).await
+    } else {
+        Ok(hosts_parser::diagnose_hosts_file(&app_handle, &content).await)
+    }
 }
 
 pub async fn write_system_hosts_from_file(
@@ -39,53 +78,48 @@ pub async fn write_system_hosts_from_file(
         .await
         .map_err(|e| e.to_string())?;
 
-    hosts_parser::validate_hosts_file(&app_handle, &content).await?;
-
-    write_system_hosts(&app_handle, content).await
+    // Validated while composing
+    write_system_hosts(app_handle, content).await
 }
 
-// Should not be called directly from the frontend. Must call hosts_parser::validate_hosts_file before.
+// Should not be called directly from the frontend.
+// The composed file is validated and written to a temporary file, which is then installed,
+// either as the whole system hosts file or as the Hedit block inside it.
 pub async fn write_system_hosts(app_handle: &AppHandle, content: String) -> Result<(), String> {
-    let platform = tauri_plugin_os::platform();
-
-    let final_content = format!(
-        "# This file was generated by the Hedit app\n# Generated on: {}\n# --------------\n",
+    let (temp_file, mut file) = TempFile::create("hedit-hosts")?;
+    write!(
+        file,
+        "{}\n# Generated on: {}\n# --------------\n",
+        GENERATED_HEADER,
         chrono::Local::now()
-    ) + &hosts_parser::parse_hosts_file(app_handle, &content).await?;
-
-    if platform == "linux" {
-        return update_hosts_file_sudo(final_content).await;
-    } else if platform == "macos" {
-        return update_hosts_file(final_content).await;
+    )
+    .map_err(|e| e.to_string())?;
+    hosts_parser::parse_hosts_file(app_handle, &content, file).await?;
+
+    // In managed block mode, entries added by other tools outside the block are kept
+    let is_managed_block = settings_store::get_settings_store_config_bool(
+        app_handle,
+        ConfigKey::ManagedBlockMode,
+        false,
+    )
+    .unwrap_or(false);
+    let installed = if is_managed_block {
+        let (block_file, file) = TempFile::create("hedit-hosts")?;
+        managed_block::wrap_in_system_hosts(temp_file.path(), file).await?;
+        block_file
     } else {
-        return Err(format!("Unsupported platform: {}", platform));
-    }
-}
-
-pub async fn update_hosts_file(content: String) -> Result<(), String> {
-    std::fs::write("/etc/hosts", &content).map_err(|e| e.to_string())?;
-    Ok(())
-}
-
-pub async fn update_hosts_file_sudo(content: String) -> Result<(), String> {
-    // Write content to temporary file first
-    let temp_path = "/tmp/hedit_hosts_update";
-    std::fs::write(temp_path, &content).map_err(|e| e.to_string())?;
-
-    // Use pkexec with cp to copy temp file to /etc/hosts
-    let output = Command::new("pkexec")
-        .arg("cp")
-        .arg(temp_path)
-        .arg("/etc/hosts")
-        .output()
-        .map_err(|e| e.to_string())?;
+        temp_file
+    };
 
-    // Clean up temp file
-    let _ = std::fs::remove_file(temp_path);
+    hosts_backups::backup_system_hosts(app_handle).await?;
+    hosts_watcher::install_and_record(app_handle, installed.path()).await?;
 
-    if output.status.success() {
-        Ok(())
-    } else {
-        Err(String::from_utf8_lossy(&output.stderr).to_string())
+    if is_managed_block {
+        managed_block::report_conflicts(app_handle).await;
+    }
+    // Copies of lists that no file includes anymore would otherwise stay forever
+    if let Err(e) = remote_cache::prune(app_handle).await {
+        eprintln!("Error pruning the remote hosts cache: {}", e);
     }
+    Ok(())
 }
@@ -0,0 +1,190 @@
+use serde::Deserialize;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+use crate::hosts_document::{Directive, Entry, HostsDocument, LineKind};
+use crate::ip_address::ScopedIp;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FormatOptions {
+    /// Sort each run of consecutive entries by IP, then by first hostname
+    pub sort_entries: bool,
+    /// Drop entries with the same IP and hostnames as an earlier one
+    pub collapse_duplicates: bool,
+}
+
+/// Formats `content` canonically: entries are column-aligned within each run of consecutive
+/// entries, whitespace is normalized, runs of blank lines are collapsed and trailing comments
+/// start with `# `. Comment lines are kept as they are, apart from surrounding whitespace.
+pub fn format_hosts(content: &str, options: &FormatOptions) -> String {
+    let document = HostsDocument::parse(content);
+    let mut seen = HashSet::new();
+    let mut lines: Vec<String> = Vec::new();
+    let mut run: Vec<&Entry> = Vec::new();
+
+    for line in &document.lines {
+        if let LineKind::Entry(entry) = &line.kind {
+            let key: Vec<&str> = std::iter::once(entry.ip.text)
+                .chain(entry.hostnames.iter().map(|host| host.text))
+                .collect();
+            if !options.collapse_duplicates || seen.insert(key) {
+                run.push(entry);
+            }
+            continue;
+        }
+
+        write_run(&mut lines, &mut run, options);
+        match &line.kind {
+            LineKind::Blank => {
+                if lines.last().is_some_and(|last| !last.is_empty()) {
+                    lines.push(String::new());
+                }
+            }
+            LineKind::Comment(comment) => lines.push(comment.text.to_string()),
+            LineKind::Directive(directive) => lines.push(format_directive(directive)),
+            LineKind::MalformedDirective => lines.push(line.raw.trim().to_string()),
+            LineKind::Entry(_) => {}
+        }
+    }
+    write_run(&mut lines, &mut run, options);
+
+    while lines.last().is_some_and(|last| last.is_empty()) {
+        lines.pop();
+    }
+
+    let mut result = lines.join("\n");
+    if content.ends_with('\n') && !result.is_empty() {
+        result.push('\n');
+    }
+    result
+}
+
+/// Writes a run of consecutive entries with aligned columns and empties it
+fn write_run(lines: &mut Vec<String>, run: &mut Vec<&Entry>, options: &FormatOptions) {
+    if options.sort_entries {
+        run.sort_by(|a, b| compare_entries(a, b));
+    }
+
+    let ip_width = run
+        .iter()
+        .map(|entry| entry.ip.text.chars().count())
+        .max()
+        .unwrap_or(0);
+    let bodies: Vec<String> = run
+        .iter()
+        .map(|entry| {
+            if entry.hostnames.is_empty() {
+                return entry.ip.text.to_string();
+            }
+            let hostnames: Vec<&str> = entry.hostnames.iter().map(|host| host.text).collect();
+            format!(
+                "{:<width$} {}",
+                entry.ip.text,
+                hostnames.join(" "),
+                width = ip_width
+            )
+        })
+        .collect();
+    let body_width = bodies
+        .iter()
+        .map(|body| body.chars().count())
+        .max()
+        .unwrap_or(0);
+
+    for (entry, body) in run.drain(..).zip(bodies) {
+        lines.push(match entry.comment {
+            Some(comment) => format!(
+                "{:<width$} {}",
+                body,
+                format_comment(comment.text),
+                width = body_width
+            ),
+            None => body,
+        });
+    }
+}
+
+/// Orders entries by address, IPv4 before IPv6 and invalid ones last, then by first hostname
+fn compare_entries(a: &Entry, b: &Entry) -> Ordering {
+    let address = |entry: &Entry| ScopedIp::parse(entry.ip.text).map(|ip| ip.addr);
+    let hostname = |entry: &Entry| entry.hostnames.first().map(|host| host.text.to_lowercase());
+
+    match (address(a), address(b)) {
+        (Some(x), Some(y)) => x.cmp(&y),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => a.ip.text.cmp(b.ip.text),
+    }
+    .then_with(|| hostname(a).cmp(&hostname(b)))
+}
+
+fn format_directive(directive: &Directive) -> String {
+    let mut result = if directive.param.text.is_empty() {
+        format!("@{}", directive.name.text)
+    } else {
+        format!("@{}({})", directive.name.text, directive.param.text)
+    };
+    if let Some(comment) = directive.comment {
+        result.push(' ');
+        result.push_str(&format_comment(comment.text));
+    }
+    result
+}
+
+/// `#comment` becomes `# comment`
+fn format_comment(comment: &str) -> String {
+    let text = comment.trim_end();
+    match text.strip_prefix('#') {
+        Some(rest) if !rest.is_empty() && !rest.starts_with([' ', '\t', '#']) => {
+            format!("# {}", rest)
+        }
+        _ => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aligns_runs_of_entries() {
+        let content = "127.0.0.1   localhost #loopback\n::1 localhost\n\n\n\n# Work\n10.0.0.1\tintranet.test\n";
+        assert_eq!(
+            format_hosts(content, &FormatOptions::default()),
+            "127.0.0.1 localhost # loopback\n::1       localhost\n\n# Work\n10.0.0.1 intranet.test\n"
+        );
+    }
+
+    #[test]
+    fn normalizes_directives_and_keeps_missing_final_newline() {
+        assert_eq!(
+            format_hosts("  @local( shared )  #team\n\n", &FormatOptions::default()),
+            "@local(shared) # team\n"
+        );
+        assert_eq!(
+            format_hosts("10.0.0.1 a.test", &FormatOptions::default()),
+            "10.0.0.1 a.test"
+        );
+    }
+
+    #[test]
+    fn sorts_and_collapses_entries() {
+        let options = FormatOptions {
+            sort_entries: true,
+            collapse_duplicates: true,
+        };
+        let content = "::1 b\n10.0.0.2 a\n10.0.0.10 a\n10.0.0.2 a\n";
+        assert_eq!(
+            format_hosts(content, &options),
+            "10.0.0.2  a\n10.0.0.10 a\n::1       b\n"
+        );
+    }
+
+    #[test]
+    fn is_idempotent() {
+        let content = "# Hosts\n127.0.0.1 localhost   #x\n@if(os=linux)\n10.0.0.1  a  b\n@endif\n";
+        let once = format_hosts(content, &FormatOptions::default());
+        assert_eq!(format_hosts(&once, &FormatOptions::default()), once);
+    }
+}
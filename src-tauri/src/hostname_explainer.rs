@@ -0,0 +1,92 @@
+use serde::Serialize;
+use std::collections::HashSet;
+use tauri::{command, Manager};
+use tokio::fs;
+
+use crate::diagnostics::Diagnostic;
+use crate::hosts_document::HostsDocument;
+use crate::hosts_installer::SYSTEM_HOSTS_PATH;
+use crate::hosts_parser::{self, HostnameMatch};
+use crate::idn;
+
+/// An entry of the installed `/etc/hosts` for the explained hostname
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemHostsEntry {
+    pub ip: String,
+    /// 1-based line number
+    pub line: usize,
+    /// Whether it is the first entry for its address family, the one resolvers use
+    pub is_effective: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HostnameExplanation {
+    pub hostname: String,
+    /// Entries of the hosts file and everything it includes, in composed order
+    pub matches: Vec<HostnameMatch>,
+    /// Problems that may hide entries, such as remote files that could not be fetched
+    pub diagnostics: Vec<Diagnostic>,
+    pub system_hosts: Vec<SystemHostsEntry>,
+}
+
+/// Entries for `hostname` in the installed hosts file
+async fn find_in_system_hosts(hostname: &str) -> Result<Vec<SystemHostsEntry>, String> {
+    let content = fs::read_to_string(SYSTEM_HOSTS_PATH)
+        .await
+        .map_err(|e| format!("Error reading {}: {}", SYSTEM_HOSTS_PATH, e))?;
+    let document = HostsDocument::parse(&content);
+
+    // Address families that already have an effective entry
+    let mut resolved = HashSet::new();
+    let mut entries = Vec::new();
+    for (line, entry) in document.entries() {
+        let is_match = entry
+            .hostnames
+            .iter()
+            .any(|host| idn::to_ascii(host.text).eq_ignore_ascii_case(hostname));
+        if !is_match {
+            continue;
+        }
+
+        entries.push(SystemHostsEntry {
+            ip: entry.ip.text.to_string(),
+            line: line.number,
+            is_effective: resolved.insert(entry.ip.text.contains(':')),
+        });
+    }
+    Ok(entries)
+}
+
+/// Explains where `hostname` resolves to with the hosts file `file_id`: every entry for it
+/// across the include graph, which one wins, and what the installed `/etc/hosts` says
+#[command]
+pub async fn explain_hostname(
+    app_handle: tauri::AppHandle,
+    file_id: String,
+    hostname: String,
+) -> Result<HostnameExplanation, String> {
+    let hostname = hostname.trim().trim_end_matches('.');
+    if hostname.is_empty() {
+        return Err("Hostname is empty".to_string());
+    }
+    let hostname = idn::to_ascii(hostname).to_ascii_lowercase();
+
+    let dir = app_handle.path().app_data_dir().unwrap();
+    let file_path = dir.join("files").join(format!("{}.hosts", file_id));
+    let content = fs::read_to_string(&file_path)
+        .await
+        .map_err(|e| format!("Error reading hosts file '{}': {}", file_id, e))?;
+
+    let (matches, diagnostics) =
+        hosts_parser::trace_hostname(&app_handle, &content, &hostname).await?;
+    let system_hosts = find_in_system_hosts(&hostname).await?;
+
+    Ok(HostnameExplanation {
+        hostname,
+        matches,
+        diagnostics,
+        system_hosts,
+    })
+}
@@ -0,0 +1,179 @@
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tauri::{command, AppHandle, Manager};
+use tokio::fs;
+
+use crate::files::GENERATED_HEADER;
+use crate::hosts_installer::SYSTEM_HOSTS_PATH;
+use crate::hosts_watcher;
+use crate::managed_block::BEGIN_MARKER;
+use crate::settings_store::{self, ConfigKey};
+use crate::temp_file::TempFile;
+
+/// Id of the hosts file from before Hedit first wrote it, never rotated out
+const ORIGINAL_ID: &str = "original";
+const DEFAULT_RETENTION: u64 = 10;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HostsBackup {
+    pub id: String,
+    /// RFC 3339
+    pub created_at: String,
+    pub size: u64,
+    pub is_original: bool,
+}
+
+fn get_backups_dir(app_handle: &AppHandle) -> Result<PathBuf, String> {
+    let dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| e.to_string())?;
+    Ok(dir.join("backups"))
+}
+
+/// Ids are timestamps, anything else would escape the backups directory
+fn backup_path(dir: &Path, id: &str) -> Result<PathBuf, String> {
+    let is_valid =
+        id == ORIGINAL_ID || (!id.is_empty() && id.chars().all(|c| c.is_ascii_digit() || c == '-'));
+    if !is_valid {
+        return Err(format!("Invalid backup id '{}'", id));
+    }
+    Ok(dir.join(format!("{}.hosts", id)))
+}
+
+/// Rotating backups, newest first
+async fn list_rotating(dir: &Path) -> Result<Vec<String>, String> {
+    let mut ids = Vec::new();
+    let mut entries = match fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(ids),
+        Err(e) => return Err(format!("Error reading backups: {}", e)),
+    };
+    while let Some(entry) = entries.next_entry().await.map_err(|e| e.to_string())? {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if let Some(id) = name.strip_suffix(".hosts") {
+            if id != ORIGINAL_ID {
+                ids.push(id.to_string());
+            }
+        }
+    }
+    // Timestamps sort chronologically
+    ids.sort_unstable_by(|a, b| b.cmp(a));
+    Ok(ids)
+}
+
+/// Whether Hedit installed `content`, as a whole file or as a managed block
+fn is_written_by_hedit(content: &[u8]) -> bool {
+    content.starts_with(GENERATED_HEADER.as_bytes())
+        || String::from_utf8_lossy(content)
+            .lines()
+            .any(|line| line.trim() == BEGIN_MARKER)
+}
+
+/// Copies the installed hosts file into the backups before it is replaced.
+/// The first file that Hedit did not generate is kept as the original, later ones rotate,
+/// keeping as many as the retention setting allows.
+pub async fn backup_system_hosts(app_handle: &AppHandle) -> Result<(), String> {
+    let content = match fs::read(SYSTEM_HOSTS_PATH).await {
+        Ok(content) => content,
+        // Nothing to lose
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(format!("Error reading {}: {}", SYSTEM_HOSTS_PATH, e)),
+    };
+
+    let dir = get_backups_dir(app_handle)?;
+    fs::create_dir_all(&dir)
+        .await
+        .map_err(|e| format!("Error creating backups directory: {}", e))?;
+
+    let original = dir.join(format!("{}.hosts", ORIGINAL_ID));
+    if !is_written_by_hedit(&content) && !original.exists() {
+        fs::write(&original, &content)
+            .await
+            .map_err(|e| format!("Error backing up {}: {}", SYSTEM_HOSTS_PATH, e))?;
+    }
+
+    let mut ids = list_rotating(&dir).await?;
+    // Writing the same file again, such as after a sync without changes, is not worth a backup
+    let latest = match ids.first() {
+        Some(id) => fs::read(dir.join(format!("{}.hosts", id))).await.ok(),
+        None => None,
+    };
+    if latest.as_ref() != Some(&content) {
+        let id = Local::now().format("%Y%m%d-%H%M%S-%3f").to_string();
+        fs::write(dir.join(format!("{}.hosts", id)), &content)
+            .await
+            .map_err(|e| format!("Error backing up {}: {}", SYSTEM_HOSTS_PATH, e))?;
+        ids.insert(0, id);
+    }
+
+    let retention = settings_store::get_settings_store_config_u64(
+        app_handle,
+        ConfigKey::HostsBackupRetention,
+        DEFAULT_RETENTION,
+    )
+    .unwrap_or(DEFAULT_RETENTION);
+    for id in ids.iter().skip(retention as usize) {
+        fs::remove_file(dir.join(format!("{}.hosts", id)))
+            .await
+            .map_err(|e| format!("Error removing backup '{}': {}", id, e))?;
+    }
+    Ok(())
+}
+
+/// Backups of the system hosts file, newest first and the original last
+#[command]
+pub async fn list_hosts_backups(app_handle: tauri::AppHandle) -> Result<Vec<HostsBackup>, String> {
+    let dir = get_backups_dir(&app_handle)?;
+    let mut ids = list_rotating(&dir).await?;
+    ids.push(ORIGINAL_ID.to_string());
+
+    let mut backups = Vec::new();
+    for id in ids {
+        let metadata = match fs::metadata(dir.join(format!("{}.hosts", id))).await {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(format!("Error reading backup '{}': {}", id, e)),
+        };
+        let created_at: DateTime<Local> = metadata.modified().map_err(|e| e.to_string())?.into();
+
+        backups.push(HostsBackup {
+            is_original: id == ORIGINAL_ID,
+            id,
+            created_at: created_at.to_rfc3339(),
+            size: metadata.len(),
+        });
+    }
+    Ok(backups)
+}
+
+/// Content of the backup `id`
+#[command]
+pub async fn read_hosts_backup(app_handle: tauri::AppHandle, id: String) -> Result<String, String> {
+    let path = backup_path(&get_backups_dir(&app_handle)?, &id)?;
+    let content = fs::read(&path)
+        .await
+        .map_err(|e| format!("Error reading backup '{}': {}", id, e))?;
+    Ok(String::from_utf8_lossy(&content).to_string())
+}
+
+/// Installs the backup `id` as the system hosts file.
+/// The file it replaces is backed up first, so a restore can be rolled back too.
+#[command]
+pub async fn restore_hosts_backup(app_handle: tauri::AppHandle, id: String) -> Result<(), String> {
+    let path = backup_path(&get_backups_dir(&app_handle)?, &id)?;
+    let content = fs::read(&path)
+        .await
+        .map_err(|e| format!("Error reading backup '{}': {}", id, e))?;
+
+    // Copied first, backing up the current file may rotate this backup out
+    let (temp_file, mut file) = TempFile::create("hedit-restore")?;
+    file.write_all(&content).map_err(|e| e.to_string())?;
+    drop(file);
+
+    backup_system_hosts(&app_handle).await?;
+    hosts_watcher::install_and_record(&app_handle, temp_file.path()).await
+}
@@ -0,0 +1,174 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use tauri::{command, AppHandle, Manager};
+use tokio::fs;
+
+use crate::hosts_document::{HostsLine, LineKind};
+use crate::hosts_installer::SYSTEM_HOSTS_PATH;
+use crate::hosts_parser;
+use crate::idn;
+use crate::ip_address::ScopedIp;
+use crate::temp_file::{LineReader, TempFile};
+
+/// One side of a diff
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum DiffSource {
+    /// The entries of a Hedit file itself, without its includes
+    File { id: String },
+    /// A Hedit file with its includes expanded, as it would be installed
+    Composed { id: String },
+    /// Hosts content given directly, such as the last saved version of a file
+    Content { content: String },
+    /// The installed `/etc/hosts`
+    System,
+}
+
+/// Canonical text of an address, as `::0001` and `::1` are the same address
+pub fn normalize_ip(text: &str) -> String {
+    match ScopedIp::parse(text) {
+        Some(ScopedIp {
+            addr,
+            zone: Some(zone),
+        }) => format!("{}%{}", addr, zone),
+        Some(ScopedIp { addr, zone: None }) => addr.to_string(),
+        None => text.to_string(),
+    }
+}
+
+/// Address each hostname resolves to, keyed by lowercase punycode hostname and IPv6-ness.
+/// Like resolvers, only the first entry for a name and address family counts.
+#[derive(Default)]
+struct Mappings(BTreeMap<(String, bool), String>);
+
+impl Mappings {
+    fn add_line(&mut self, line: &HostsLine<'_>) {
+        let LineKind::Entry(entry) = &line.kind else {
+            return;
+        };
+
+        let ip = normalize_ip(entry.ip.text);
+        let is_ipv6 = entry.ip.text.contains(':');
+        for host in &entry.hostnames {
+            let key = (idn::to_ascii(host.text).to_ascii_lowercase(), is_ipv6);
+            self.0.entry(key).or_insert_with(|| ip.clone());
+        }
+    }
+
+    fn from_content(content: &str) -> Self {
+        let mut mappings = Mappings::default();
+        for (index, raw) in content.lines().enumerate() {
+            mappings.add_line(&HostsLine::parse(index + 1, raw));
+        }
+        mappings
+    }
+
+    async fn load(app_handle: &AppHandle, source: &DiffSource) -> Result<Self, String> {
+        match source {
+            DiffSource::File { id } => {
+                Ok(Mappings::from_content(&read_file(app_handle, id).await?))
+            }
+            DiffSource::Composed { id } => {
+                let content = read_file(app_handle, id).await?;
+                let (composed, file) = TempFile::create("hedit-diff")?;
+                // Problems are not a reason to refuse a diff, whatever composes is compared
+                hosts_parser::compose_hosts_file(app_handle, &content, file).await?;
+
+                let mut mappings = Mappings::default();
+                let mut reader = LineReader::open(composed.path())?;
+                while let Some((number, raw)) = reader.next_line()? {
+                    mappings.add_line(&HostsLine::parse(number, &raw));
+                }
+                Ok(mappings)
+            }
+            DiffSource::Content { content } => Ok(Mappings::from_content(content)),
+            DiffSource::System => {
+                let content = fs::read_to_string(SYSTEM_HOSTS_PATH)
+                    .await
+                    .map_err(|e| format!("Error reading {}: {}", SYSTEM_HOSTS_PATH, e))?;
+                Ok(Mappings::from_content(&content))
+            }
+        }
+    }
+}
+
+async fn read_file(app_handle: &AppHandle, id: &str) -> Result<String, String> {
+    let dir = app_handle.path().app_data_dir().unwrap();
+    let file_path = dir.join("files").join(format!("{}.hosts", id));
+    fs::read_to_string(&file_path)
+        .await
+        .map_err(|e| format!("Error reading hosts file '{}': {}", id, e))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    /// Resolves to another address
+    Repointed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HostnameChange {
+    pub hostname: String,
+    pub is_ipv6: bool,
+    pub kind: ChangeKind,
+    pub old_ip: Option<String>,
+    pub new_ip: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HostsDiff {
+    /// Sorted by hostname, IPv4 before IPv6
+    pub changes: Vec<HostnameChange>,
+    /// Number of hostnames resolving to the same address on both sides
+    pub unchanged: usize,
+}
+
+fn diff(old: &Mappings, new: &Mappings) -> HostsDiff {
+    let keys: BTreeSet<&(String, bool)> = old.0.keys().chain(new.0.keys()).collect();
+    let mut changes = Vec::new();
+    let mut unchanged = 0;
+
+    for key in keys {
+        let (old_ip, new_ip) = (old.0.get(key), new.0.get(key));
+        let kind = match (old_ip, new_ip) {
+            (None, Some(_)) => ChangeKind::Added,
+            (Some(_), None) => ChangeKind::Removed,
+            (Some(a), Some(b)) if a != b => ChangeKind::Repointed,
+            _ => {
+                unchanged += 1;
+                continue;
+            }
+        };
+        changes.push(HostnameChange {
+            hostname: key.0.clone(),
+            is_ipv6: key.1,
+            kind,
+            old_ip: old_ip.cloned(),
+            new_ip: new_ip.cloned(),
+        });
+    }
+
+    HostsDiff { changes, unchanged }
+}
+
+/// Compares two hosts files given as content, see `diff_hosts`
+pub fn diff_contents(old: &str, new: &str) -> HostsDiff {
+    diff(&Mappings::from_content(old), &Mappings::from_content(new))
+}
+
+/// Compares what hostnames resolve to with `old` and `new`, ignoring comments and whitespace
+#[command]
+pub async fn diff_hosts(
+    app_handle: tauri::AppHandle,
+    old: DiffSource,
+    new: DiffSource,
+) -> Result<HostsDiff, String> {
+    let old = Mappings::load(&app_handle, &old).await?;
+    let new = Mappings::load(&app_handle, &new).await?;
+    Ok(diff(&old, &new))
+}
@@ -0,0 +1,277 @@
+use std::fmt;
+
+/// Location of a token inside a hosts file.
+/// `line` is 1-based, `start` and `end` are byte offsets within the raw line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub line: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A piece of text borrowed from the source together with its location
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Token<'a> {
+    pub text: &'a str,
+    pub span: Span,
+}
+
+/// `IP hostname... # comment`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry<'a> {
+    pub ip: Token<'a>,
+    pub hostnames: Vec<Token<'a>>,
+    pub comment: Option<Token<'a>>,
+}
+
+/// `@name(param) # comment`, or `@name # comment` with an empty `param`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Directive<'a> {
+    pub name: Token<'a>,
+    pub param: Token<'a>,
+    pub comment: Option<Token<'a>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LineKind<'a> {
+    Blank,
+    Comment(Token<'a>),
+    Entry(Entry<'a>),
+    Directive(Directive<'a>),
+    /// A line starting with `@` that could not be parsed as `@name(param)` or `@name`
+    MalformedDirective,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostsLine<'a> {
+    /// 1-based line number
+    pub number: usize,
+    /// The line exactly as it appears in the source, without the line feed
+    pub raw: &'a str,
+    pub kind: LineKind<'a>,
+}
+
+/// Parsed hosts file. Keeps every line of the source, so `to_string()`
+/// reproduces the original content byte for byte.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostsDocument<'a> {
+    pub lines: Vec<HostsLine<'a>>,
+}
+
+impl<'a> HostsDocument<'a> {
+    pub fn parse(content: &'a str) -> Self {
+        let lines = content
+            .split('\n')
+            .enumerate()
+            .map(|(index, raw)| HostsLine::parse(index + 1, raw))
+            .collect();
+
+        HostsDocument { lines }
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&HostsLine<'a>, &Entry<'a>)> {
+        self.lines.iter().filter_map(|line| match &line.kind {
+            LineKind::Entry(entry) => Some((line, entry)),
+            _ => None,
+        })
+    }
+
+    pub fn directives(&self) -> impl Iterator<Item = (&HostsLine<'a>, &Directive<'a>)> {
+        self.lines.iter().filter_map(|line| match &line.kind {
+            LineKind::Directive(directive) => Some((line, directive)),
+            _ => None,
+        })
+    }
+}
+
+impl<'a> HostsLine<'a> {
+    /// Parses line `number` on its own, for files that are read line by line
+    pub fn parse(number: usize, raw: &'a str) -> Self {
+        let trimmed = raw.trim();
+
+        let kind = if trimmed.is_empty() {
+            LineKind::Blank
+        } else if trimmed.starts_with('#') {
+            LineKind::Comment(token(number, raw, trimmed))
+        } else if trimmed.starts_with('@') {
+            parse_directive(number, raw, trimmed)
+                .map(LineKind::Directive)
+                .unwrap_or(LineKind::MalformedDirective)
+        } else {
+            LineKind::Entry(parse_entry(number, raw, trimmed))
+        };
+
+        HostsLine { number, raw, kind }
+    }
+
+    /// Span of the line without leading and trailing whitespace
+    pub fn content_span(&self) -> Span {
+        token(self.number, self.raw, self.raw.trim()).span
+    }
+}
+
+impl fmt::Display for HostsDocument<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, line) in self.lines.iter().enumerate() {
+            if index > 0 {
+                f.write_str("\n")?;
+            }
+            f.write_str(line.raw)?;
+        }
+        Ok(())
+    }
+}
+
+/// Builds a token for `text`, which must be a subslice of `raw`
+fn token<'a>(line: usize, raw: &'a str, text: &'a str) -> Token<'a> {
+    let start = text.as_ptr() as usize - raw.as_ptr() as usize;
+    Token {
+        text,
+        span: Span {
+            line,
+            start,
+            end: start + text.len(),
+        },
+    }
+}
+
+fn parse_entry<'a>(number: usize, raw: &'a str, trimmed: &'a str) -> Entry<'a> {
+    let (body, comment) = match trimmed.find('#') {
+        Some(index) => (
+            &trimmed[..index],
+            Some(token(number, raw, &trimmed[index..])),
+        ),
+        None => (trimmed, None),
+    };
+
+    let mut parts = body.split_whitespace().map(|part| token(number, raw, part));
+    // `trimmed` is non-empty and does not start with '#', so there is always a first token
+    let ip = parts.next().unwrap_or_else(|| token(number, raw, trimmed));
+
+    Entry {
+        ip,
+        hostnames: parts.collect(),
+        comment,
+    }
+}
+
+fn parse_directive<'a>(number: usize, raw: &'a str, trimmed: &'a str) -> Option<Directive<'a>> {
+    let rest = &trimmed[1..];
+    let Some(open) = rest.find('(') else {
+        // Directives without a parameter, such as `@endif`
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let (name, tail) = (&rest[..end], rest[end..].trim());
+        if name.is_empty()
+            || !name.chars().all(|c| c.is_ascii_alphanumeric())
+            || !(tail.is_empty() || tail.starts_with('#'))
+        {
+            return None;
+        }
+
+        return Some(Directive {
+            name: token(number, raw, name),
+            param: token(number, raw, &rest[end..end]),
+            comment: (!tail.is_empty()).then(|| token(number, raw, tail)),
+        });
+    };
+    let name = &rest[..open];
+    let inner = &rest[open + 1..];
+
+    // The parameter ends at the first ')' that is only followed by whitespace or a
+    // whitespace-separated comment, so URLs containing parentheses or '#' are kept intact.
+    let closing = |strict: bool| {
+        inner.match_indices(')').find_map(|(index, _)| {
+            let after = &inner[index + 1..];
+            let tail = after.trim();
+            let is_end = tail.is_empty()
+                || (tail.starts_with('#') && (!strict || after.starts_with(char::is_whitespace)));
+            is_end.then(|| (&inner[..index], tail))
+        })
+    };
+    let (param, tail) = closing(true).or_else(|| closing(false))?;
+    let param = param.trim();
+
+    if name.is_empty() || param.is_empty() {
+        return None;
+    }
+
+    Some(Directive {
+        name: token(number, raw, name),
+        param: token(number, raw, param),
+        comment: (!tail.is_empty()).then(|| token(number, raw, tail)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_byte_for_byte() {
+        let sources = [
+            "",
+            "\n",
+            "127.0.0.1 localhost\n",
+            "127.0.0.1 localhost",
+            "127.0.0.1\tlocalhost  # loopback\r\n::1 localhost\r\n",
+            "  # indented comment\n\n\n@remote(https://example.com/list#v2) # pinned\n",
+            "@if(os=linux)\n10.0.0.1   a.test\n@endif\n   \n",
+        ];
+        for source in sources {
+            assert_eq!(HostsDocument::parse(source).to_string(), source);
+        }
+    }
+
+    #[test]
+    fn parses_entries_with_spans() {
+        let line = HostsLine::parse(3, "  10.0.0.1 a.test b.test # note\r");
+        let LineKind::Entry(entry) = line.kind else {
+            panic!("expected an entry, got {:?}", line.kind);
+        };
+        assert_eq!(entry.ip.text, "10.0.0.1");
+        assert_eq!(
+            entry.ip.span,
+            Span {
+                line: 3,
+                start: 2,
+                end: 10
+            }
+        );
+        let hostnames: Vec<&str> = entry.hostnames.iter().map(|host| host.text).collect();
+        assert_eq!(hostnames, ["a.test", "b.test"]);
+        assert_eq!(entry.comment.map(|comment| comment.text), Some("# note"));
+    }
+
+    #[test]
+    fn keeps_hash_inside_directive_parameters() {
+        let line = HostsLine::parse(1, "@remote(https://example.com/list#v2) # pinned");
+        let LineKind::Directive(directive) = line.kind else {
+            panic!("expected a directive, got {:?}", line.kind);
+        };
+        assert_eq!(directive.name.text, "remote");
+        assert_eq!(directive.param.text, "https://example.com/list#v2");
+        assert_eq!(
+            directive.comment.map(|comment| comment.text),
+            Some("# pinned")
+        );
+    }
+
+    #[test]
+    fn parses_directives_without_parameter() {
+        let line = HostsLine::parse(1, "@endif # linux");
+        let LineKind::Directive(directive) = line.kind else {
+            panic!("expected a directive, got {:?}", line.kind);
+        };
+        assert_eq!(directive.name.text, "endif");
+        assert_eq!(directive.param.text, "");
+
+        assert_eq!(
+            HostsLine::parse(1, "@remote(").kind,
+            LineKind::MalformedDirective
+        );
+        assert_eq!(
+            HostsLine::parse(1, "@endif trailing").kind,
+            LineKind::MalformedDirective
+        );
+    }
+}
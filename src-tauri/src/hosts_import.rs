@@ -0,0 +1,72 @@
+use serde::Serialize;
+use tauri::{command, Manager};
+use tokio::fs;
+
+use crate::diagnostics::Diagnostic;
+use crate::files::GENERATED_HEADER;
+use crate::hosts_installer::SYSTEM_HOSTS_PATH;
+use crate::hosts_parser;
+use crate::managed_block::{BEGIN_MARKER, END_MARKER};
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportedHostsFile {
+    pub id: String,
+    pub content: String,
+    /// Problems in the imported content, the file is created even with errors so they can be fixed
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Removes what Hedit added when it installed the file:
+/// its generated header and the markers of its managed block
+fn strip_hedit_lines(content: &str) -> String {
+    let mut lines = content.split_inclusive('\n').peekable();
+    let mut stripped = String::with_capacity(content.len());
+
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim();
+        if trimmed == BEGIN_MARKER || trimmed == END_MARKER {
+            continue;
+        }
+        if trimmed == GENERATED_HEADER {
+            // `# Generated on: ...` and the separator below it
+            if lines
+                .next_if(|line| line.starts_with("# Generated on:"))
+                .is_some()
+            {
+                lines.next_if(|line| line.trim() == "# --------------");
+            }
+            continue;
+        }
+        stripped.push_str(line);
+    }
+    stripped
+}
+
+/// Creates a local hosts file from the installed `/etc/hosts`, so nothing in it is lost
+/// when a Hedit file is first activated. The frontend adds it to the files list.
+#[command]
+pub async fn import_system_hosts(
+    app_handle: tauri::AppHandle,
+) -> Result<ImportedHostsFile, String> {
+    let system = fs::read(SYSTEM_HOSTS_PATH)
+        .await
+        .map_err(|e| format!("Error reading {}: {}", SYSTEM_HOSTS_PATH, e))?;
+    let content = strip_hedit_lines(&String::from_utf8_lossy(&system));
+    let diagnostics = hosts_parser::diagnose_hosts_file(&app_handle, &content).await;
+
+    let id = uuid::Uuid::new_v4().to_string();
+    let dir = app_handle.path().app_data_dir().unwrap().join("files");
+    fs::create_dir_all(&dir)
+        .await
+        .map_err(|e| format!("Error creating files directory: {}", e))?;
+    fs::write(dir.join(format!("{}.hosts", id)), &content)
+        .await
+        .map_err(|e| format!("Error creating hosts file: {}", e))?;
+
+    Ok(ImportedHostsFile {
+        id,
+        content,
+        diagnostics,
+    })
+}
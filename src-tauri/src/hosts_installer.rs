@@ -0,0 +1,141 @@
+#[cfg(unix)]
+use std::fs::{self, File, OpenOptions};
+#[cfg(unix)]
+use std::io::{self, Read};
+#[cfg(unix)]
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt, PermissionsExt};
+use std::path::Path;
+use std::process::Command;
+
+pub const SYSTEM_HOSTS_PATH: &str = "/etc/hosts";
+
+/// Run as root through pkexec with the composed file as `$1`.
+/// The new file is written next to the real target of `/etc/hosts`, given the owner, mode
+/// and SELinux context of the file it replaces, then renamed over it, so readers only ever
+/// see the old or the new content.
+const INSTALL_SCRIPT: &str = r#"set -eu
+source="$1"
+target=$(readlink -f /etc/hosts)
+temp=$(mktemp "$(dirname "$target")/.hosts.hedit.XXXXXX")
+trap 'rm -f "$temp"' EXIT
+cat "$source" > "$temp"
+chown --reference="$target" "$temp"
+chmod --reference="$target" "$temp"
+if command -v selinuxenabled > /dev/null && selinuxenabled; then
+    chcon --reference="$target" "$temp"
+fi
+sync "$temp"
+mv -f "$temp" "$target"
+trap - EXIT
+cmp -s "$source" "$target"
+"#;
+
+/// Replaces the system hosts file with the content of `source`
+pub async fn install_hosts_file(source: &Path) -> Result<(), String> {
+    let platform = tauri_plugin_os::platform();
+
+    if platform == "linux" {
+        return install_with_pkexec(source);
+    }
+    // Ownership and permissions are only kept with the Unix file APIs
+    #[cfg(unix)]
+    if platform == "macos" {
+        return install_directly(source);
+    }
+    Err(format!("Unsupported platform: {}", platform))
+}
+
+fn install_with_pkexec(source: &Path) -> Result<(), String> {
+    let output = Command::new("pkexec")
+        .arg("/bin/sh")
+        .arg("-c")
+        .arg(INSTALL_SCRIPT)
+        .arg("hedit-install")
+        .arg(source)
+        .output()
+        .map_err(|e| e.to_string())?;
+
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if stderr.trim().is_empty() {
+        // `cmp -s` is silent, anything else that fails explains itself
+        Err(format!(
+            "{} does not match the generated file after installing it",
+            SYSTEM_HOSTS_PATH
+        ))
+    } else {
+        Err(stderr.to_string())
+    }
+}
+
+#[cfg(unix)]
+/// Installs without elevating, for when the app itself may write the hosts file
+fn install_directly(source: &Path) -> Result<(), String> {
+    let target = fs::canonicalize(SYSTEM_HOSTS_PATH)
+        .map_err(|e| format!("Error resolving {}: {}", SYSTEM_HOSTS_PATH, e))?;
+    let metadata =
+        fs::metadata(&target).map_err(|e| format!("Error reading {}: {}", target.display(), e))?;
+
+    match replace_file(source, &target, &metadata) {
+        Ok(()) => {}
+        // Write access may have been granted on the file only, not on its directory
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+            // Not atomic, but the file keeps its owner, mode and attributes
+            let mut content = File::open(source).map_err(|e| e.to_string())?;
+            let mut hosts = File::create(&target)
+                .map_err(|e| format!("Error writing {}: {}", target.display(), e))?;
+            io::copy(&mut content, &mut hosts)
+                .and_then(|_| hosts.sync_all())
+                .map_err(|e| format!("Error writing {}: {}", target.display(), e))?;
+        }
+        Err(e) => return Err(format!("Error installing {}: {}", target.display(), e)),
+    }
+
+    verify_installed(source, &target)
+}
+
+#[cfg(unix)]
+/// Writes `source` to a new file in the directory of `target` and renames it over `target`
+fn replace_file(source: &Path, target: &Path, metadata: &fs::Metadata) -> io::Result<()> {
+    let dir = target.parent().unwrap_or(Path::new("/"));
+    let temp = dir.join(format!(".hosts.hedit.{}", uuid::Uuid::new_v4()));
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&temp)?;
+
+    let result = (|| {
+        io::copy(&mut File::open(source)?, &mut file)?;
+        std::os::unix::fs::fchown(&file, Some(metadata.uid()), Some(metadata.gid()))?;
+        file.set_permissions(fs::Permissions::from_mode(metadata.mode() & 0o7777))?;
+        file.sync_all()?;
+        fs::rename(&temp, target)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+#[cfg(unix)]
+fn verify_installed(source: &Path, target: &Path) -> Result<(), String> {
+    let read = |path: &Path| -> Result<Vec<u8>, String> {
+        let mut content = Vec::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_end(&mut content))
+            .map_err(|e| format!("Error reading {}: {}", path.display(), e))?;
+        Ok(content)
+    };
+
+    if read(source)? == read(target)? {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} does not match the generated file after installing it",
+            target.display()
+        ))
+    }
+}
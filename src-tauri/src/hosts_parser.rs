@@ -1,215 +1,1763 @@
 use once_cell::sync::Lazy;
-use std::collections::HashSet;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufWriter, Write};
 use std::net::IpAddr;
-use std::str::FromStr;
+use std::path::{Path, PathBuf};
 use tauri::AppHandle;
 use tauri::Manager;
 use tokio::fs;
 use url::Url;
 
-use crate::remote_hosts;
+use crate::conditionals::{self, Condition, Platform};
+use crate::diagnostics::{
+    first_error, Diagnostic, DiagnosticCode, DiagnosticSource, IncludeFrame, Severity,
+};
+use crate::expansion::Expansion;
+use crate::hosts_document::{Entry, HostsDocument, HostsLine, LineKind, Span, Token};
+use crate::idn;
+use crate::ip_address::{self, EmbeddedIpv4, ScopedIp};
+use crate::list_formats::{ConversionReport, Converter, ListFormat};
+use crate::remote_cache::{self, CachedFile};
+use crate::remote_hosts::{self, RemoteOptions};
+use crate::settings_store::{self, ConfigKey};
+use crate::temp_file::LineReader;
+use crate::variables::{self, Variables};
 
 static HOSTNAME_REGEX: Lazy<regex::Regex> = Lazy::new(|| {
     regex::Regex::new(r"^(([a-zA-Z0-9_]|[a-zA-Z0-9_][a-zA-Z0-9_-]*[a-zA-Z0-9_])\.)*([A-Za-z0-9]|[A-Za-z0-9][A-Za-z0-9-]*[A-Za-z0-9])$").unwrap()
 });
 
 #[inline]
-fn is_ignored_host(h: &str) -> bool {
+pub fn is_ignored_host(h: &str) -> bool {
     matches!(h, "localhost" | "broadcasthost" | "localdomain")
 }
 
-fn is_valid_ip(ip: &str) -> bool {
-    IpAddr::from_str(ip).is_ok() || matches!(ip, "fe80::1%lo0")
+/// Unicode hostnames are checked in their punycode form
+pub fn is_valid_hostname(hostname: &str) -> bool {
+    HOSTNAME_REGEX.is_match(&idn::to_ascii(hostname))
 }
 
-fn is_valid_hostname(hostname: &str) -> bool {
-    HOSTNAME_REGEX.is_match(hostname)
+/// How deep `@local` files may include other files
+const MAX_INCLUDE_DEPTH: usize = 8;
+
+/// How many remote files are downloaded at the same time, unless set in the settings
+const DEFAULT_FETCH_CONCURRENCY: u64 = 4;
+
+/// Whether `host` matches one of the `@exclude` patterns.
+/// `*.example.com` matches every subdomain of `example.com`, other patterns match exactly.
+fn is_excluded(host: &str, excludes: &[String]) -> bool {
+    let host = idn::to_ascii(host).to_ascii_lowercase();
+    excludes
+        .iter()
+        .any(|pattern| match pattern.strip_prefix("*.") {
+            Some(suffix) => host
+                .strip_suffix(suffix)
+                .is_some_and(|label| label.ends_with('.')),
+            None => host == *pattern,
+        })
 }
 
-fn extract_func_param(line: &str) -> Option<(&str, &str)> {
-    let func_name = line.split('(').next().unwrap_or("").trim_start_matches('@');
-    let func_param = line
-        .split('(')
-        .nth(1)
-        .and_then(|s| s.strip_suffix(')'))
-        .unwrap_or("");
-    if func_name.is_empty() || func_param.is_empty() {
-        None
-    } else {
-        Some((func_name, func_param))
+/// Lowercase punycode form of an `@exclude` pattern, as compared by `is_excluded`
+fn normalize_exclude(pattern: &str) -> String {
+    match pattern.strip_prefix("*.") {
+        Some(suffix) => format!("*.{}", idn::to_ascii(suffix).to_ascii_lowercase()),
+        None => idn::to_ascii(pattern).to_ascii_lowercase(),
     }
 }
 
-fn get_local_file_path(app_handle: &AppHandle, file_name: &str) -> std::path::PathBuf {
+/// Directory of the local hosts files, which `@local` includes by name
+fn get_files_dir(app_handle: &AppHandle) -> PathBuf {
     let dir = app_handle.path().app_data_dir().unwrap();
-    dir.join("files").join(file_name.to_string() + ".hosts")
+    dir.join("files")
 }
 
-pub async fn validate_hosts_file(app_handle: &AppHandle, content: &str) -> Result<(), String> {
-    let mut host_map: HashSet<&str> = HashSet::new();
+fn get_local_file_path(files_dir: &Path, file_name: &str) -> PathBuf {
+    files_dir.join(file_name.to_string() + ".hosts")
+}
 
-    for (line_num, line) in content.lines().enumerate() {
-        let trimmed_line = line.trim();
+/// How hostnames defined more than once are handled when composing a file,
+/// set with `@duplicates(policy)`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    /// Reject the file
+    Error,
+    /// Keep the first occurrence
+    FirstWins,
+    /// Keep the last occurrence
+    LastWins,
+    /// Keep the entry from the file itself, otherwise the first occurrence
+    PreferInline,
+}
 
-        // Skip empty lines and comments directly.
-        if trimmed_line.is_empty() || trimmed_line.starts_with('#') {
-            continue;
+impl DuplicatePolicy {
+    const VALUES: &'static str = "error, first-wins, last-wins, prefer-inline";
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "error" => Some(DuplicatePolicy::Error),
+            "first-wins" => Some(DuplicatePolicy::FirstWins),
+            "last-wins" => Some(DuplicatePolicy::LastWins),
+            "prefer-inline" => Some(DuplicatePolicy::PreferInline),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            DuplicatePolicy::Error => "error",
+            DuplicatePolicy::FirstWins => "first-wins",
+            DuplicatePolicy::LastWins => "last-wins",
+            DuplicatePolicy::PreferInline => "prefer-inline",
         }
+    }
 
-        if trimmed_line.starts_with('@') {
-            let (func_name, func_param) = extract_func_param(trimmed_line).ok_or_else(|| {
-                format!("Invalid function syntax at line {}: {}", line_num + 1, line)
-            })?;
+    /// Policy declared in `document`, `Error` if there is none
+    fn of(document: &HostsDocument<'_>) -> Self {
+        document
+            .directives()
+            .filter(|(_, directive)| directive.name.text == "duplicates")
+            .filter_map(|(_, directive)| Self::parse(directive.param.text))
+            .last()
+            .unwrap_or(DuplicatePolicy::Error)
+    }
+}
 
-            if func_name == "remote" {
-                let fetch_url = Url::parse(&func_param)
-                    .map_err(|e| format!("Invalid URL at line {}: {}", line_num + 1, e))?;
+/// A problem found on a line, turned into a `Diagnostic` once the line is checked
+type Finding = (Severity, DiagnosticCode, String, Span);
 
-                if fetch_url.scheme() != "https" {
-                    return Err(format!(
-                        "Insecure URL. Only HTTPS is allowed at line {}.",
-                        line_num + 1
-                    ));
+/// Checks documents one after another, keeping track of hostnames across all of them
+struct Validator<'h> {
+    files_dir: &'h Path,
+    duplicate_policy: DuplicatePolicy,
+    /// How often each hostname occurs per address family, handed on to the `Deduper`
+    occurrences: HashMap<(String, bool), usize>,
+    diagnostics: Vec<Diagnostic>,
+    /// Whether `${NAME}` references may be left for a file including this one to define,
+    /// in which case the IPs and hostnames containing them are not checked
+    allow_references: bool,
+}
+
+impl<'h> Validator<'h> {
+    fn new(files_dir: &'h Path, duplicate_policy: DuplicatePolicy) -> Self {
+        Validator {
+            files_dir,
+            duplicate_policy,
+            occurrences: HashMap::new(),
+            diagnostics: Vec::new(),
+            allow_references: false,
+        }
+    }
+
+    async fn check_document(
+        &mut self,
+        document: &HostsDocument<'_>,
+        source: &DiagnosticSource,
+        included_from: &[IncludeFrame],
+        excludes: &[String],
+    ) {
+        for line in &document.lines {
+            self.check_line(line, source, included_from, excludes).await;
+        }
+    }
+
+    /// Checks a remote file that is too large to be held in memory, line by line.
+    /// Returns what could not be converted from the list's format along the way.
+    async fn check_remote(
+        &mut self,
+        remote: &RemoteBody,
+        source: &DiagnosticSource,
+        included_from: &[IncludeFrame],
+        excludes: &[String],
+    ) -> Result<ConversionReport, String> {
+        let mut lines = remote.lines()?;
+        while let Some((number, raw)) = lines.next_line()? {
+            self.check_line(
+                &HostsLine::parse(number, &raw),
+                source,
+                included_from,
+                excludes,
+            )
+            .await;
+        }
+        Ok(lines.report())
+    }
+
+    async fn check_line(
+        &mut self,
+        line: &HostsLine<'_>,
+        source: &DiagnosticSource,
+        included_from: &[IncludeFrame],
+        excludes: &[String],
+    ) {
+        let mut found = Vec::new();
+
+        match &line.kind {
+            // Skip empty lines and comments directly.
+            LineKind::Blank | LineKind::Comment(_) => {}
+            LineKind::MalformedDirective => found.push((
+                Severity::Error,
+                DiagnosticCode::InvalidDirective,
+                format!("Invalid function syntax: {}", line.raw.trim()),
+                line.content_span(),
+            )),
+            LineKind::Directive(directive) if matches!(source, DiagnosticSource::Remote(_)) => {
+                found.push((
+                    Severity::Error,
+                    DiagnosticCode::UnsupportedDirective,
+                    "Functions are not supported in remote hosts files".to_string(),
+                    line.content_span(),
+                ))
+            }
+            LineKind::Directive(directive) => {
+                let func_param = directive.param.text;
+                match directive.name.text {
+                    "else" | "endif" => {
+                        if !func_param.is_empty() {
+                            found.push((
+                                Severity::Error,
+                                DiagnosticCode::InvalidParameter,
+                                format!("@{} does not take a parameter", directive.name.text),
+                                directive.param.span,
+                            ));
+                        }
+                    }
+                    func_name @ ("remote" | "local" | "exclude" | "define" | "duplicates"
+                    | "if" | "expand")
+                        if func_param.is_empty() =>
+                    {
+                        found.push((
+                            Severity::Error,
+                            DiagnosticCode::InvalidDirective,
+                            format!("Function @{} requires a parameter", func_name),
+                            line.content_span(),
+                        ));
+                    }
+                    "remote" => match remote_hosts::parse_remote_param(func_param)
+                        .map(|(url, _)| (url, Url::parse(url)))
+                    {
+                        Err(e) => found.push((
+                            Severity::Error,
+                            DiagnosticCode::InvalidParameter,
+                            e,
+                            directive.param.span,
+                        )),
+                        Ok((url, Err(e))) => found.push((
+                            Severity::Error,
+                            DiagnosticCode::InvalidUrl,
+                            format!("Invalid URL '{}': {}", url, e),
+                            directive.param.span,
+                        )),
+                        Ok((_, Ok(fetch_url))) if fetch_url.scheme() != "https" => found.push((
+                            Severity::Error,
+                            DiagnosticCode::InsecureUrl,
+                            "Insecure URL. Only HTTPS is allowed".to_string(),
+                            directive.param.span,
+                        )),
+                        Ok(_) => {}
+                    },
+                    "local" => {
+                        let file_path = get_local_file_path(self.files_dir, func_param);
+                        if fs::metadata(&file_path).await.is_err() {
+                            found.push((
+                                Severity::Error,
+                                DiagnosticCode::LocalFileNotFound,
+                                format!("Local hosts file '{}' not found", func_param),
+                                directive.param.span,
+                            ));
+                        }
+                    }
+                    "exclude" => {
+                        let pattern = func_param.strip_prefix("*.").unwrap_or(func_param);
+                        if !is_valid_hostname(pattern) {
+                            found.push((
+                                Severity::Error,
+                                DiagnosticCode::InvalidParameter,
+                                format!("Invalid exclude pattern '{}'", func_param),
+                                directive.param.span,
+                            ));
+                        }
+                    }
+                    "define" => {
+                        if variables::parse_definition(func_param).is_none() {
+                            found.push((
+                                Severity::Error,
+                                DiagnosticCode::InvalidParameter,
+                                format!(
+                                    "Invalid variable definition '{}'. Expected NAME=value",
+                                    func_param
+                                ),
+                                directive.param.span,
+                            ));
+                        }
+                    }
+                    "if" => {
+                        if Condition::parse(func_param).is_none() {
+                            found.push((
+                                Severity::Error,
+                                DiagnosticCode::InvalidParameter,
+                                format!(
+                                    "Invalid condition '{}'. Expected one of: {}",
+                                    func_param,
+                                    Condition::SYNTAX
+                                ),
+                                directive.param.span,
+                            ));
+                        }
+                    }
+                    "expand" => match Expansion::parse(func_param) {
+                        Err(e) => found.push((
+                            Severity::Error,
+                            DiagnosticCode::InvalidParameter,
+                            e,
+                            directive.param.span,
+                        )),
+                        // Problems in generated lines are reported on the directive
+                        Ok(expansion) => {
+                            for generated in expansion.lines() {
+                                let mut generated_found = Vec::new();
+                                for (_, entry) in HostsDocument::parse(&generated).entries() {
+                                    self.check_entry(
+                                        entry,
+                                        source,
+                                        excludes,
+                                        true,
+                                        &mut generated_found,
+                                    );
+                                }
+                                found.extend(generated_found.into_iter().map(
+                                    |(severity, code, message, _)| {
+                                        (
+                                            severity,
+                                            code,
+                                            format!(
+                                                "{} in generated line '{}'",
+                                                message, generated
+                                            ),
+                                            directive.param.span,
+                                        )
+                                    },
+                                ));
+                            }
+                        }
+                    },
+                    "duplicates" => {
+                        if DuplicatePolicy::parse(func_param).is_none() {
+                            found.push((
+                                Severity::Error,
+                                DiagnosticCode::InvalidParameter,
+                                format!(
+                                    "Unknown duplicate policy '{}'. Expected one of: {}",
+                                    func_param,
+                                    DuplicatePolicy::VALUES
+                                ),
+                                directive.param.span,
+                            ));
+                        }
+                    }
+                    func_name => found.push((
+                        Severity::Error,
+                        DiagnosticCode::UnsupportedDirective,
+                        format!("Unsupported function '{}'", func_name),
+                        directive.name.span,
+                    )),
                 }
-            } else if func_name == "local" {
-                let file_path = get_local_file_path(app_handle, func_param);
-                fs::metadata(&file_path).await.map_err(|_| {
-                    format!(
-                        "Local hosts file '{}' not found at line {}",
-                        func_param,
-                        line_num + 1
-                    )
-                })?;
-            } else {
-                return Err(format!(
-                    "Unsupported function '{}' at line {}",
-                    func_name,
-                    line_num + 1
-                ));
             }
-            continue;
+            LineKind::Entry(entry) => self.check_entry(entry, source, excludes, true, &mut found),
         }
 
-        let mut parts = trimmed_line.split_whitespace();
-        let Some(ip) = parts.next() else {
-            return Err(format!(
-                "Invalid line #{} in hosts file: {}",
-                line_num + 1,
-                trimmed_line
+        self.report(line, found, source, included_from);
+    }
+
+    /// Checks an entry of an inactive `@if` branch, which is meant for another machine.
+    /// Only its syntax is checked, its hostnames do not count as duplicates.
+    fn check_inactive_line(&mut self, line: &HostsLine<'_>, source: &DiagnosticSource) {
+        let LineKind::Entry(entry) = &line.kind else {
+            return;
+        };
+        let mut found = Vec::new();
+        self.check_entry(entry, source, &[], false, &mut found);
+        self.report(line, found, source, &[]);
+    }
+
+    fn report(
+        &mut self,
+        line: &HostsLine<'_>,
+        found: Vec<Finding>,
+        source: &DiagnosticSource,
+        included_from: &[IncludeFrame],
+    ) {
+        self.diagnostics
+            .extend(found.into_iter().map(|(severity, code, message, span)| {
+                let mut diagnostic =
+                    Diagnostic::new(severity, code, message, source, line.raw, span);
+                diagnostic.included_from = included_from.to_vec();
+                diagnostic
+            }));
+    }
+
+    fn check_ip(&self, token: &Token<'_>, found: &mut Vec<Finding>) {
+        let (text, span) = (token.text, token.span);
+        if self.allow_references && text.contains("${") {
+            return;
+        }
+        let Some(ip) = ScopedIp::parse(text) else {
+            found.push((
+                Severity::Error,
+                DiagnosticCode::InvalidIp,
+                format!("Invalid IP: {}", text),
+                span,
             ));
+            return;
         };
-        if !is_valid_ip(ip) {
-            return Err(format!("Invalid IP at line {}: {}", line_num + 1, ip));
-        }
-
-        for (host_num, host) in parts.enumerate() {
-            if host.starts_with("#") && host_num >= 1 {
-                break;
-            } else if host_num >= 1 {
-                return Err(format!(
-                    "Multiple hostnames on line {} are not supported: {}",
-                    line_num + 1,
-                    line
+
+        if let Some(zone) = ip.zone {
+            let zone_span = Span {
+                start: span.end - zone.len(),
+                ..span
+            };
+            if !ip.is_link_local() {
+                found.push((
+                    Severity::Warning,
+                    DiagnosticCode::UnscopedZone,
+                    format!(
+                        "Zone '{}' is ignored for {}, which is not a link-local address",
+                        zone, ip.addr
+                    ),
+                    zone_span,
                 ));
             }
-            if is_ignored_host(host) {
-                break;
+            // The file may be meant for another machine, so this is not an error
+            if !ip_address::interface_exists(zone) {
+                found.push((
+                    Severity::Warning,
+                    DiagnosticCode::UnknownInterface,
+                    format!(
+                        "Network interface '{}' does not exist on this machine",
+                        zone
+                    ),
+                    zone_span,
+                ));
             }
-            if !is_valid_hostname(host) {
-                return Err(format!(
-                    "Invalid hostname at line {}: {}",
-                    line_num + 1,
-                    host
+        }
+
+        if let IpAddr::V6(addr) = ip.addr {
+            let message = match EmbeddedIpv4::of(&addr) {
+                Some(EmbeddedIpv4::Mapped(ipv4)) => format!(
+                    "{} is an IPv4-mapped address, resolvers may return it as IPv4 {}. Use {} in an IPv4 entry instead",
+                    text, ipv4, ipv4
+                ),
+                Some(EmbeddedIpv4::Compatible(ipv4)) => format!(
+                    "{} is a deprecated IPv4-compatible address and may be ignored by resolvers. Use {} in an IPv4 entry instead",
+                    text, ipv4
+                ),
+                None => return,
+            };
+            found.push((
+                Severity::Warning,
+                DiagnosticCode::EmbeddedIpv4,
+                message,
+                span,
+            ));
+        }
+    }
+
+    fn check_entry(
+        &mut self,
+        entry: &Entry<'_>,
+        source: &DiagnosticSource,
+        excludes: &[String],
+        check_duplicates: bool,
+        found: &mut Vec<Finding>,
+    ) {
+        self.check_ip(&entry.ip, found);
+
+        if entry.hostnames.is_empty() {
+            found.push((
+                Severity::Warning,
+                DiagnosticCode::MissingHostname,
+                format!("No hostname for IP {}", entry.ip.text),
+                entry.ip.span,
+            ));
+        }
+
+        // The same name may point to both an IPv4 and an IPv6 address,
+        // so duplicates are only checked within one address family.
+        let is_ipv6 = entry.ip.text.contains(':');
+
+        for host in &entry.hostnames {
+            if is_ignored_host(host.text) || is_excluded(host.text, excludes) {
+                continue;
+            }
+            if self.allow_references && host.text.contains("${") {
+                continue;
+            }
+            if !is_valid_hostname(host.text) {
+                found.push((
+                    Severity::Error,
+                    DiagnosticCode::InvalidHostname,
+                    format!("Invalid hostname: {}", idn::describe(host.text)),
+                    host.span,
                 ));
+                continue;
             }
 
-            if host_map.contains(host) {
-                return Err(format!(
-                    "Duplicate hostname '{}' at line {}",
-                    host,
-                    line_num + 1
+            if let Some((label, scripts)) = idn::mixed_script_label(host.text) {
+                found.push((
+                    Severity::Warning,
+                    DiagnosticCode::MixedScriptHostname,
+                    format!(
+                        "Hostname {} mixes {} characters in label '{}', it may imitate another name",
+                        idn::describe(host.text),
+                        scripts.join(" and "),
+                        label
+                    ),
+                    host.span,
                 ));
+            }
+
+            if !check_duplicates {
+                continue;
+            }
+            // Unicode names and their punycode form are the same host
+            let occurrences = self
+                .occurrences
+                .entry((idn::to_ascii(host.text).into_owned(), is_ipv6))
+                .or_default();
+            *occurrences += 1;
+            if *occurrences == 1 {
+                continue;
+            }
+            if self.duplicate_policy == DuplicatePolicy::Error {
+                found.push((
+                    Severity::Error,
+                    DiagnosticCode::DuplicateHostname,
+                    format!("Duplicate hostname {}", idn::describe(host.text)),
+                    host.span,
+                ));
+            } else if *source == DiagnosticSource::Inline {
+                // Included lists commonly overlap, only point out the file's own duplicates
+                found.push((
+                    Severity::Warning,
+                    DiagnosticCode::DuplicateHostname,
+                    format!(
+                        "Duplicate hostname {}, resolved by the {} policy",
+                        idn::describe(host.text),
+                        self.duplicate_policy.as_str()
+                    ),
+                    host.span,
+                ));
+            }
+        }
+    }
+}
+
+/// Validates `content` on its own, without expanding its includes
+async fn check_inline(files_dir: &Path, content: &str) -> Vec<Diagnostic> {
+    let evaluation = conditionals::evaluate(
+        content,
+        &Platform::current(),
+        &DiagnosticSource::Inline,
+        &[],
+    );
+    let substitution = variables::substitute(
+        &evaluation.content,
+        &Variables::new(),
+        &DiagnosticSource::Inline,
+        &[],
+    );
+    let document = HostsDocument::parse(&substitution.content);
+
+    let mut validator = Validator {
+        allow_references: true,
+        ..Validator::new(files_dir, DuplicatePolicy::of(&document))
+    };
+    validator
+        .check_document(&document, &DiagnosticSource::Inline, &[], &[])
+        .await;
+    // Lines for other machines are commented out above, a typo in them would go unnoticed here
+    let original = HostsDocument::parse(content);
+    for &number in &evaluation.inactive_lines {
+        validator.check_inactive_line(&original.lines[number - 1], &DiagnosticSource::Inline);
+    }
+
+    let mut diagnostics = evaluation.diagnostics;
+    // A file including this one may define them, which only composing can tell
+    diagnostics.extend(
+        substitution
+            .diagnostics
+            .iter()
+            .map(|diagnostic| Diagnostic {
+                severity: Severity::Warning,
+                message: format!(
+                    "{}, unless a file including this one defines it",
+                    diagnostic.message
+                ),
+                ..diagnostic.clone()
+            }),
+    );
+    // Variables may be meant for included files, which are not looked at here
+    if !document.directives().any(|(_, d)| d.name.text == "local") {
+        diagnostics.extend(substitution.unused(&substitution.used));
+    }
+    diagnostics.extend(validator.diagnostics);
+    diagnostics
+}
+
+/// Returns every problem found in `content`, without expanding its includes
+pub async fn diagnose_hosts_file(app_handle: &AppHandle, content: &str) -> Vec<Diagnostic> {
+    check_inline(&get_files_dir(app_handle), content).await
+}
+
+pub async fn validate_hosts_file(app_handle: &AppHandle, content: &str) -> Result<(), String> {
+    match first_error(&check_inline(&get_files_dir(app_handle), content).await) {
+        Some(error) => Err(error.to_string()),
+        None => Ok(()),
+    }
+}
+
+/// Decides which occurrence of a duplicated hostname is written to the composed output
+struct Deduper {
+    policy: DuplicatePolicy,
+    /// Occurrences not written yet, as counted by the `Validator`. `FirstWins` and
+    /// `PreferInline` set it to zero once the hostname is written.
+    remaining: HashMap<(String, bool), usize>,
+    /// Hostnames defined in the composed file itself, used by `PreferInline`
+    inline: HashSet<(String, bool)>,
+}
+
+impl Deduper {
+    /// `inline` is the composed file itself, `occurrences` counts every hostname of the composition
+    fn new(
+        policy: DuplicatePolicy,
+        inline: &HostsDocument<'_>,
+        occurrences: HashMap<(String, bool), usize>,
+    ) -> Self {
+        let mut deduper = Deduper {
+            policy,
+            remaining: occurrences,
+            inline: HashSet::new(),
+        };
+        if policy == DuplicatePolicy::PreferInline {
+            for_each_entry(inline, |entry| deduper.inline.extend(host_keys(entry, &[])));
+        }
+        deduper
+    }
+
+    fn keep(&mut self, key: (String, bool), is_inline: bool) -> bool {
+        if self.policy == DuplicatePolicy::PreferInline && !is_inline && self.inline.contains(&key)
+        {
+            return false;
+        }
+        // Invalid hostnames are not counted, they fail validation anyway
+        let Some(remaining) = self.remaining.get_mut(&key) else {
+            return true;
+        };
+        match self.policy {
+            DuplicatePolicy::Error => true,
+            DuplicatePolicy::LastWins => {
+                *remaining = remaining.saturating_sub(1);
+                *remaining == 0
+            }
+            DuplicatePolicy::FirstWins | DuplicatePolicy::PreferInline => {
+                std::mem::take(remaining) > 0
+            }
+        }
+    }
+
+    /// Writes `line`, leaving out excluded hostnames and those that lose against another occurrence
+    fn write_line(
+        &mut self,
+        output: &mut Output<impl Write>,
+        line: &HostsLine<'_>,
+        is_inline: bool,
+        excludes: &[String],
+    ) -> io::Result<()> {
+        let LineKind::Entry(entry) = &line.kind else {
+            return output.line(line.raw);
+        };
+
+        let is_ipv6 = entry.ip.text.contains(':');
+        let mut kept = Vec::new();
+        let mut excluded = Vec::new();
+        let mut dropped = Vec::new();
+        for host in entry.hostnames.iter().map(|host| host.text) {
+            if is_ignored_host(host) {
+                kept.push(host);
+            } else if is_excluded(host, excludes) {
+                excluded.push(host);
+            } else if self.keep((idn::to_ascii(host).into_owned(), is_ipv6), is_inline) {
+                kept.push(host);
             } else {
-                host_map.insert(host);
+                dropped.push(host);
+            }
+        }
+
+        let is_ascii = kept.iter().all(|host| host.is_ascii());
+        if excluded.is_empty() && dropped.is_empty() && is_ascii {
+            return output.line(line.raw);
+        }
+
+        if !excluded.is_empty() {
+            output.line(&format!(
+                "# Excluded: {} {}",
+                entry.ip.text,
+                excluded.join(" ")
+            ))?;
+        }
+        if !dropped.is_empty() {
+            output.line(&format!(
+                "# Duplicate dropped ({}): {} {}",
+                self.policy.as_str(),
+                entry.ip.text,
+                dropped.join(" ")
+            ))?;
+        }
+        if !kept.is_empty() {
+            // Resolvers only understand the punycode form of Unicode hostnames
+            let kept: Vec<Cow<str>> = kept.into_iter().map(idn::to_ascii).collect();
+            let mut rewritten = format!("{} {}", entry.ip.text, kept.join(" "));
+            if let Some(comment) = &entry.comment {
+                rewritten.push(' ');
+                rewritten.push_str(comment.text);
             }
+            output.line(&rewritten)?;
         }
+        Ok(())
     }
+}
 
-    Ok(())
+/// Lines generated by `line` if it is an `@expand` directive
+fn expanded_lines(line: &HostsLine<'_>) -> Vec<String> {
+    match &line.kind {
+        LineKind::Directive(directive) if directive.name.text == "expand" => {
+            Expansion::parse(directive.param.text)
+                .map(|expansion| expansion.lines().collect())
+                .unwrap_or_default()
+        }
+        _ => Vec::new(),
+    }
 }
 
-pub async fn parse_hosts_file(app_handle: &AppHandle, content: &str) -> Result<String, String> {
-    // TODO: maybe we want to save to a temporary file instead of building a big string in memory?
-    let mut result = String::with_capacity(content.len());
+/// Calls `f` for every entry of `document`, including those generated by `@expand`
+fn for_each_entry(document: &HostsDocument<'_>, mut f: impl FnMut(&Entry<'_>)) {
+    for line in &document.lines {
+        if let LineKind::Entry(entry) = &line.kind {
+            f(entry);
+            continue;
+        }
+        for generated in expanded_lines(line) {
+            for (_, entry) in HostsDocument::parse(&generated).entries() {
+                f(entry);
+            }
+        }
+    }
+}
 
-    // TODO: cache fetched remote files to avoid multiple fetches
-    for (line_num, line) in content.lines().enumerate() {
-        let trimmed_line = line.trim();
+/// Duplicate lookup keys of the hostnames of `entry` that are written to the output
+fn host_keys<'a>(
+    entry: &'a Entry<'_>,
+    excludes: &'a [String],
+) -> impl Iterator<Item = (String, bool)> + 'a {
+    let is_ipv6 = entry.ip.text.contains(':');
+    entry
+        .hostnames
+        .iter()
+        .filter(|host| !is_ignored_host(host.text) && !is_excluded(host.text, excludes))
+        .map(move |host| (idn::to_ascii(host.text).into_owned(), is_ipv6))
+}
 
-        if trimmed_line.starts_with('@') {
-            let (func_name, func_param) = extract_func_param(trimmed_line).ok_or_else(|| {
-                format!("Invalid function syntax at line {}: {}", line_num + 1, line)
-            })?;
+/// Writes lines separated by line feeds, without one after the last line
+struct Output<W: Write> {
+    writer: W,
+    started: bool,
+}
 
-            if func_name == "remote" {
-                let mut response = remote_hosts::fetch_remote_url(app_handle, func_param)
-                    .await
-                    .map_err(|e| format!("{} '{}' at line {}", e, func_param, line_num + 1,))?;
+impl<W: Write> Output<W> {
+    fn new(writer: W) -> Self {
+        Output {
+            writer,
+            started: false,
+        }
+    }
 
-                result.push_str(&format!(
-                    "# Begin included remote hosts file: {}\n",
-                    func_param
+    fn line(&mut self, line: &str) -> io::Result<()> {
+        if self.started {
+            self.writer.write_all(b"\n")?;
+        }
+        self.started = true;
+        self.writer.write_all(line.as_bytes())
+    }
+}
+
+/// Cached copy of a remote file, read line by line so that large lists are never held in memory
+struct RemoteBody {
+    file: CachedFile,
+    /// Lists in other formats are converted to hosts syntax as they are read
+    format: ListFormat,
+    /// Set with `@remote(url, ip=...)`, applied as lines are read so the cache keeps the original
+    sink_ip: Option<IpAddr>,
+    /// Index of the part with the `@remote` directive and the directive itself,
+    /// on which lines that cannot be converted are summed up
+    parent: usize,
+    directive: (String, Span),
+}
+
+impl RemoteBody {
+    fn lines(&self) -> Result<RemoteLines<'_>, String> {
+        Ok(RemoteLines {
+            remote: self,
+            reader: LineReader::open(&self.file.path)?,
+            converter: Converter::new(self.format),
+        })
+    }
+}
+
+struct RemoteLines<'r> {
+    remote: &'r RemoteBody,
+    reader: LineReader,
+    converter: Converter,
+}
+
+impl RemoteLines<'_> {
+    /// Next line of the file in hosts syntax, with blocking entries pointed at the sink IP
+    fn next_line(&mut self) -> Result<Option<(usize, String)>, String> {
+        let Some((number, raw)) = self.reader.next_line()? else {
+            return Ok(None);
+        };
+
+        let line = self.converter.convert(number, &raw);
+        let line = match self.remote.sink_ip {
+            Some(sink_ip) => remote_hosts::rewrite_sink_ip(&line, sink_ip),
+            None => line.into_owned(),
+        };
+        Ok(Some((number, line)))
+    }
+
+    fn report(self) -> ConversionReport {
+        self.converter.report()
+    }
+}
+
+/// A file taking part in a composition
+struct Part {
+    source: DiagnosticSource,
+    /// Include directives leading to this file, outermost first
+    included_from: Vec<IncludeFrame>,
+    /// Empty for remote files, which are kept in `remote`
+    content: String,
+    remote: Option<RemoteBody>,
+    /// `@exclude` patterns of the files including this one
+    excludes: Vec<String>,
+    /// Variables visible to the files included by this one
+    variables: Variables,
+    /// Parts included by this one, keyed by the line number of their directive
+    includes: HashMap<usize, usize>,
+}
+
+/// Remote include whose file is fetched after every include has been found
+struct PendingRemote {
+    /// Index of the part the file is attached to
+    part: usize,
+    /// Index of the part with the `@remote` directive
+    parent: usize,
+    line: usize,
+    url: String,
+    raw: String,
+    span: Span,
+    sink_ip: Option<IpAddr>,
+}
+
+/// Problem with an include directive of `part`
+fn include_diagnostic(
+    part: &Part,
+    severity: Severity,
+    code: DiagnosticCode,
+    message: String,
+    raw: &str,
+    span: Span,
+) -> Diagnostic {
+    let mut diagnostic = Diagnostic::new(severity, code, message, &part.source, raw, span);
+    diagnostic.included_from = part.included_from.clone();
+    diagnostic
+}
+
+/// Loads every local file included by `content` from `files_dir`, following `@local`
+/// includes recursively. Includes that cannot be expanded are reported in `diagnostics`.
+/// Remote includes are returned to be fetched together, their parts are left empty.
+async fn collect_local_parts(
+    files_dir: &Path,
+    content: &str,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> (Vec<Part>, Vec<PendingRemote>) {
+    let platform = Platform::current();
+    let mut evaluation = conditionals::evaluate(content, &platform, &DiagnosticSource::Inline, &[]);
+    diagnostics.append(&mut evaluation.diagnostics);
+    let mut root = variables::substitute(
+        &evaluation.content,
+        &Variables::new(),
+        &DiagnosticSource::Inline,
+        &[],
+    );
+    diagnostics.append(&mut root.diagnostics);
+
+    let mut parts = vec![Part {
+        source: DiagnosticSource::Inline,
+        included_from: Vec::new(),
+        content: std::mem::take(&mut root.content),
+        remote: None,
+        excludes: Vec::new(),
+        variables: root.variables.clone(),
+        includes: HashMap::new(),
+    }];
+    let mut substitutions = vec![root];
+    let mut pending: Vec<PendingRemote> = Vec::new();
+
+    let mut index = 0;
+    while index < parts.len() {
+        // Remote files cannot include other files
+        if matches!(parts[index].source, DiagnosticSource::Remote(_)) {
+            index += 1;
+            continue;
+        }
+
+        let document = HostsDocument::parse(&parts[index].content);
+        let mut excludes = parts[index].excludes.clone();
+        excludes.extend(
+            document
+                .directives()
+                .filter(|(_, d)| d.name.text == "exclude" && !d.param.text.is_empty())
+                .map(|(_, d)| normalize_exclude(d.param.text)),
+        );
+        let directives: Vec<(usize, String, String, Span, String)> = document
+            .directives()
+            // Missing parameters are reported by the validator
+            .filter(|(_, d)| matches!(d.name.text, "remote" | "local") && !d.param.text.is_empty())
+            .map(|(line, d)| {
+                (
+                    line.number,
+                    d.name.text.to_string(),
+                    d.param.text.to_string(),
+                    d.param.span,
+                    line.raw.to_string(),
+                )
+            })
+            .collect();
+
+        for (line_num, func_name, func_param, span, raw) in directives {
+            // Invalid options are reported by the validator
+            let (func_param, options) = if func_name == "remote" {
+                match remote_hosts::parse_remote_param(&func_param) {
+                    Ok((url, options)) => (url.to_string(), options),
+                    Err(_) => continue,
+                }
+            } else {
+                (func_param, RemoteOptions::default())
+            };
+
+            let part = &parts[index];
+            let mut included_from = part.included_from.clone();
+            included_from.push(IncludeFrame {
+                source: part.source.clone(),
+                line: line_num,
+            });
+
+            let mut report = |code, message| {
+                diagnostics.push(include_diagnostic(
+                    part,
+                    Severity::Error,
+                    code,
+                    message,
+                    &raw,
+                    span,
                 ));
-                while let Some(chunk) = response.chunk().await.map_err(|e| {
+            };
+
+            if included_from.len() > MAX_INCLUDE_DEPTH {
+                report(
+                    DiagnosticCode::IncludeDepthExceeded,
                     format!(
-                        "Error reading response body from remote hosts file '{}' at line {}: {}",
-                        func_param,
-                        line_num + 1,
-                        e
-                    )
-                })? {
-                    result.push_str(std::str::from_utf8(&chunk).map_err(|e| {
+                        "Maximum include depth of {} exceeded by '{}'",
+                        MAX_INCLUDE_DEPTH, func_param
+                    ),
+                );
+                continue;
+            }
+
+            let source = if func_name == "remote" {
+                DiagnosticSource::Remote(func_param.clone())
+            } else {
+                DiagnosticSource::Local(func_param.clone())
+            };
+
+            if let Some(start) = included_from.iter().position(|f| f.source == source) {
+                let cycle: Vec<&str> = included_from[start..]
+                    .iter()
+                    .filter_map(|frame| match &frame.source {
+                        DiagnosticSource::Local(name) => Some(name.as_str()),
+                        _ => None,
+                    })
+                    .chain(std::iter::once(func_param.as_str()))
+                    .collect();
+                report(
+                    DiagnosticCode::IncludeCycle,
+                    format!("Include cycle detected: {}", cycle.join(" -> ")),
+                );
+                continue;
+            }
+
+            if func_name == "remote" {
+                // Remote files are used as they are, their body is attached once fetched
+                let child = parts.len();
+                parts[index].includes.insert(line_num, child);
+                pending.push(PendingRemote {
+                    part: child,
+                    parent: index,
+                    line: line_num,
+                    url: func_param,
+                    raw,
+                    span,
+                    sink_ip: options.sink_ip,
+                });
+                parts.push(Part {
+                    source,
+                    included_from,
+                    content: String::new(),
+                    remote: None,
+                    excludes: excludes.clone(),
+                    variables: Variables::new(),
+                    includes: HashMap::new(),
+                });
+                continue;
+            }
+
+            let file_path = get_local_file_path(files_dir, &func_param);
+            let included = match fs::read_to_string(&file_path).await {
+                Ok(file_content) => file_content,
+                // Reported by the validator
+                Err(_) => continue,
+            };
+
+            let (included, variables) = {
+                let mut evaluation =
+                    conditionals::evaluate(&included, &platform, &source, &included_from);
+                diagnostics.append(&mut evaluation.diagnostics);
+                let mut substitution = variables::substitute(
+                    &evaluation.content,
+                    &parts[index].variables,
+                    &source,
+                    &included_from,
+                );
+                diagnostics.append(&mut substitution.diagnostics);
+                let content = std::mem::take(&mut substitution.content);
+                let variables = substitution.variables.clone();
+                substitutions.push(substitution);
+                (content, variables)
+            };
+
+            let child = parts.len();
+            parts[index].includes.insert(line_num, child);
+            parts.push(Part {
+                source,
+                included_from,
+                content: included,
+                remote: None,
+                excludes: excludes.clone(),
+                variables,
+                includes: HashMap::new(),
+            });
+        }
+
+        index += 1;
+    }
+
+    let used: HashSet<String> = substitutions
+        .iter()
+        .flat_map(|substitution| substitution.used.iter().cloned())
+        .collect();
+    for substitution in &substitutions {
+        diagnostics.extend(substitution.unused(&used));
+    }
+
+    (parts, pending)
+}
+
+/// Loads every file included by `content`, following `@local` includes recursively.
+/// Includes that cannot be expanded are reported in `diagnostics` and left out.
+async fn collect_parts(
+    app_handle: &AppHandle,
+    content: &str,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Vec<Part> {
+    let (mut parts, pending) =
+        collect_local_parts(&get_files_dir(app_handle), content, diagnostics).await;
+
+    // Remote files cannot include other files, so they are all fetched together.
+    // Each URL is fetched once, even if several files include it.
+    let mut urls: Vec<String> = Vec::new();
+    for remote in &pending {
+        if !urls.contains(&remote.url) {
+            urls.push(remote.url.clone());
+        }
+    }
+    let limit = settings_store::get_settings_store_config_u64(
+        app_handle,
+        ConfigKey::RemoteFetchConcurrency,
+        DEFAULT_FETCH_CONCURRENCY,
+    )
+    .unwrap_or(DEFAULT_FETCH_CONCURRENCY);
+    let fetched: HashMap<String, Result<CachedFile, String>> = urls
+        .iter()
+        .cloned()
+        .zip(remote_cache::fetch_all(app_handle, &urls, limit as usize).await)
+        .collect();
+
+    for remote in pending {
+        let parent = &parts[remote.parent];
+        match &fetched[&remote.url] {
+            Ok(file) => {
+                if let Some(e) = &file.fetch_error {
+                    diagnostics.push(include_diagnostic(
+                        parent,
+                        Severity::Warning,
+                        DiagnosticCode::StaleRemoteCache,
                         format!(
-                            "Error decoding chunk from remote hosts file '{}' at line {}: {}",
-                            func_param,
-                            line_num + 1,
+                            "Using the copy cached on {}: {}",
+                            file.fetched_at.format("%Y-%m-%d %H:%M"),
                             e
-                        )
-                    })?);
+                        ),
+                        &remote.raw,
+                        remote.span,
+                    ));
                 }
-                result.push('\n');
-                result.push_str("# End included remote hosts file\n\n");
-            } else if func_name == "local" {
-                let file_path = get_local_file_path(app_handle, func_param);
-                let file_content = fs::read_to_string(&file_path).await.map_err(|_| {
-                    format!(
-                        "Local hosts file '{}' not found at line {}",
-                        func_param,
-                        line_num + 1
-                    )
-                })?;
-
-                result.push_str(&format!(
-                    "# Begin included local hosts file: {}\n",
-                    func_param
+                parts[remote.part].remote = Some(RemoteBody {
+                    file: file.clone(),
+                    format: ListFormat::detect_file(&file.path).unwrap_or(ListFormat::Hosts),
+                    sink_ip: remote.sink_ip,
+                    parent: remote.parent,
+                    directive: (remote.raw, remote.span),
+                });
+            }
+            Err(e) => {
+                diagnostics.push(include_diagnostic(
+                    parent,
+                    Severity::Error,
+                    DiagnosticCode::RemoteFetchFailed,
+                    e.clone(),
+                    &remote.raw,
+                    remote.span,
                 ));
-                result.push_str(&file_content);
-                result.push('\n');
-                result.push_str("# End included local hosts file\n\n");
+                // Left out of the composed file
+                parts[remote.parent].includes.remove(&remote.line);
             }
-            continue;
         }
+    }
+
+    parts
+}
+
+/// Writes part `index` with its includes expanded in place
+fn write_part(
+    output: &mut Output<impl Write>,
+    parts: &[Part],
+    documents: &[HostsDocument<'_>],
+    deduper: &mut Deduper,
+    index: usize,
+) -> Result<(), String> {
+    let part = &parts[index];
+    let write_error = |e: io::Error| format!("Error writing composed hosts file: {}", e);
 
-        result.push_str(line);
-        result.push('\n');
+    if let Some(remote) = &part.remote {
+        let mut lines = remote.lines()?;
+        while let Some((number, raw)) = lines.next_line()? {
+            deduper
+                .write_line(
+                    output,
+                    &HostsLine::parse(number, &raw),
+                    false,
+                    &part.excludes,
+                )
+                .map_err(write_error)?;
+        }
+        return Ok(());
     }
 
-    // Remove trailing newline
-    if result.ends_with('\n') {
-        result.pop();
+    for line in &documents[index].lines {
+        match &line.kind {
+            LineKind::Entry(_) | LineKind::Comment(_) | LineKind::Blank => deduper
+                .write_line(output, line, index == 0, &part.excludes)
+                .map_err(write_error)?,
+            // Directives in remote files are rejected by the validator
+            LineKind::Directive(_) | LineKind::MalformedDirective => {
+                let Some(&child) = part.includes.get(&line.number) else {
+                    let generated = expanded_lines(line);
+                    if !generated.is_empty() {
+                        output
+                            .line(&format!("# Expanded from {}", line.raw.trim()))
+                            .map_err(write_error)?;
+                    }
+                    for text in &generated {
+                        for generated_line in &HostsDocument::parse(text).lines {
+                            deduper
+                                .write_line(output, generated_line, index == 0, &part.excludes)
+                                .map_err(write_error)?;
+                        }
+                    }
+                    // Other directives only affect how the file is composed
+                    continue;
+                };
+                let (kind, name) = match (&parts[child].source, &parts[child].remote) {
+                    (DiagnosticSource::Remote(url), Some(remote)) => (
+                        "remote",
+                        format!("{} ({})", url, remote.file.describe_age()),
+                    ),
+                    (DiagnosticSource::Remote(url), None) => ("remote", url.clone()),
+                    (DiagnosticSource::Local(name), _) => ("local", name.clone()),
+                    (DiagnosticSource::Inline, _) => continue,
+                };
+
+                output
+                    .line(&format!("# Begin included {} hosts file: {}", kind, name))
+                    .map_err(write_error)?;
+                write_part(output, parts, documents, deduper, child)?;
+                output
+                    .line(&format!("# End included {} hosts file", kind))
+                    .and_then(|_| output.line(""))
+                    .map_err(write_error)?;
+            }
+        }
     }
 
-    validate_hosts_file(app_handle, &result).await?;
+    Ok(())
+}
+
+/// Calls `f` with the part index, line number and entry of every entry of part `index`,
+/// in the order they are written to the composed file
+fn visit_entries<F: FnMut(usize, usize, &Entry<'_>)>(
+    parts: &[Part],
+    documents: &[HostsDocument<'_>],
+    index: usize,
+    f: &mut F,
+) -> Result<(), String> {
+    if let Some(remote) = &parts[index].remote {
+        let mut lines = remote.lines()?;
+        while let Some((number, raw)) = lines.next_line()? {
+            if let LineKind::Entry(entry) = &HostsLine::parse(number, &raw).kind {
+                f(index, number, entry);
+            }
+        }
+        return Ok(());
+    }
+
+    for line in &documents[index].lines {
+        if let LineKind::Entry(entry) = &line.kind {
+            f(index, line.number, entry);
+        } else if let Some(&child) = parts[index].includes.get(&line.number) {
+            visit_entries(parts, documents, child, f)?;
+        } else {
+            for generated in expanded_lines(line) {
+                for (_, entry) in HostsDocument::parse(&generated).entries() {
+                    f(index, line.number, entry);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// What happens to an entry for a traced hostname in the composed file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchStatus {
+    /// First entry written for the name and address family, the one resolvers use
+    Wins,
+    /// Dropped by the duplicate policy, or written after the winning entry
+    Shadowed,
+    /// Left out by an `@exclude` pattern
+    Excluded,
+}
+
+/// An entry defining a traced hostname
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HostnameMatch {
+    pub ip: String,
+    pub source: DiagnosticSource,
+    /// Include directives leading to the file, outermost first
+    pub included_from: Vec<IncludeFrame>,
+    /// 1-based line number, that of the `@expand` directive for generated entries
+    pub line: usize,
+    pub status: MatchStatus,
+}
 
-    Ok(result)
+/// Every entry for `hostname` in `content` and the files it includes, in composed order,
+/// with the problems found while loading the includes
+pub async fn trace_hostname(
+    app_handle: &AppHandle,
+    content: &str,
+    hostname: &str,
+) -> Result<(Vec<HostnameMatch>, Vec<Diagnostic>), String> {
+    let mut diagnostics = Vec::new();
+    let parts = collect_parts(app_handle, content, &mut diagnostics).await;
+    let documents: Vec<HostsDocument> = parts
+        .iter()
+        .map(|part| HostsDocument::parse(&part.content))
+        .collect();
+
+    let wanted = idn::to_ascii(hostname).to_ascii_lowercase();
+    // With `LastWins` a later entry decides, so the entries are collected first
+    let mut candidates = Vec::new();
+    let mut occurrences = HashMap::new();
+    visit_entries(&parts, &documents, 0, &mut |index, line, entry| {
+        let part = &parts[index];
+        let is_ipv6 = entry.ip.text.contains(':');
+        for host in &entry.hostnames {
+            if idn::to_ascii(host.text).to_ascii_lowercase() != wanted {
+                continue;
+            }
+
+            let is_ignored = is_ignored_host(host.text);
+            let excluded = !is_ignored && is_excluded(host.text, &part.excludes);
+            let key = (idn::to_ascii(host.text).into_owned(), is_ipv6);
+            if !is_ignored && !excluded {
+                *occurrences.entry(key.clone()).or_default() += 1;
+            }
+            let status = if excluded {
+                MatchStatus::Excluded
+            } else {
+                MatchStatus::Shadowed
+            };
+            candidates.push((
+                index,
+                is_ignored,
+                key,
+                HostnameMatch {
+                    ip: entry.ip.text.to_string(),
+                    source: part.source.clone(),
+                    included_from: part.included_from.clone(),
+                    line,
+                    status,
+                },
+            ));
+        }
+    })?;
+
+    // Decided as in `Deduper::write_line`
+    let mut deduper = Deduper::new(
+        DuplicatePolicy::of(&documents[0]),
+        &documents[0],
+        occurrences,
+    );
+    // Address families that already have a winning entry
+    let mut resolved = HashSet::new();
+    let matches = candidates
+        .into_iter()
+        .map(|(index, is_ignored, key, mut found)| {
+            let is_ipv6 = key.1;
+            if found.status != MatchStatus::Excluded
+                && (is_ignored || deduper.keep(key, index == 0))
+                && resolved.insert(is_ipv6)
+            {
+                found.status = MatchStatus::Wins;
+            }
+            found
+        })
+        .collect();
+
+    Ok((matches, diagnostics))
+}
+
+/// Evaluates `@if` blocks, expands `@remote` and `@local` includes and `@expand` ranges,
+/// validates every part with its own source and writes the result to `output`.
+/// Remote files are kept in the on-disk cache and streamed through line by line: their
+/// format is detected from the first lines, then they are read once to validate them and
+/// once to write them. Memory use does not grow with the size of the included lists, only
+/// with the number of distinct hostnames, as every hostname is kept with its number of
+/// occurrences to tell duplicates apart.
+pub async fn compose_hosts_file(
+    app_handle: &AppHandle,
+    content: &str,
+    output: impl Write,
+) -> Result<Vec<Diagnostic>, String> {
+    let mut diagnostics = Vec::new();
+    let parts = collect_parts(app_handle, content, &mut diagnostics).await;
+    write_composed(&get_files_dir(app_handle), &parts, &mut diagnostics, output).await?;
+    Ok(diagnostics)
+}
+
+/// Validates `parts` into `diagnostics` and writes them to `output` with their includes expanded
+async fn write_composed(
+    files_dir: &Path,
+    parts: &[Part],
+    diagnostics: &mut Vec<Diagnostic>,
+    output: impl Write,
+) -> Result<(), String> {
+    let documents: Vec<HostsDocument> = parts
+        .iter()
+        .map(|part| HostsDocument::parse(&part.content))
+        .collect();
+    // A policy inside an inactive `@if` branch does not apply
+    let duplicate_policy = DuplicatePolicy::of(&documents[0]);
+
+    let mut validator = Validator::new(files_dir, duplicate_policy);
+    let mut reports = Vec::new();
+    for (document, part) in documents.iter().zip(parts) {
+        match &part.remote {
+            Some(remote) => {
+                let report = validator
+                    .check_remote(remote, &part.source, &part.included_from, &part.excludes)
+                    .await?;
+                if report.unconverted_count > 0 {
+                    let (raw, span) = &remote.directive;
+                    reports.push(include_diagnostic(
+                        &parts[remote.parent],
+                        Severity::Warning,
+                        DiagnosticCode::UnconvertedLines,
+                        report.summary(),
+                        raw,
+                        *span,
+                    ));
+                }
+            }
+            None => {
+                validator
+                    .check_document(document, &part.source, &part.included_from, &part.excludes)
+                    .await
+            }
+        }
+    }
+    diagnostics.extend(reports);
+    diagnostics.extend(validator.diagnostics);
+
+    let mut output = Output::new(BufWriter::new(output));
+    let mut deduper = Deduper::new(duplicate_policy, &documents[0], validator.occurrences);
+    write_part(&mut output, parts, &documents, &mut deduper, 0)?;
+    output
+        .writer
+        .flush()
+        .map_err(|e| format!("Error writing composed hosts file: {}", e))
+}
+
+/// Composes `content` into `output`, failing on the first validation error.
+/// Remote files that could not be fetched are all reported together.
+pub async fn parse_hosts_file(
+    app_handle: &AppHandle,
+    content: &str,
+    output: impl Write,
+) -> Result<(), String> {
+    let diagnostics = compose_hosts_file(app_handle, content, output).await?;
+
+    let fetch_errors: Vec<String> = diagnostics
+        .iter()
+        .filter(|d| d.code == DiagnosticCode::RemoteFetchFailed)
+        .map(|d| d.to_string())
+        .collect();
+    if fetch_errors.len() > 1 {
+        return Err(format!(
+            "Failed to fetch {} remote hosts files:\n{}",
+            fetch_errors.len(),
+            fetch_errors.join("\n")
+        ));
+    }
+
+    match first_error(&diagnostics) {
+        Some(error) => Err(error.to_string()),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Local hosts files in a directory of their own, removed when dropped
+    struct FilesDir(PathBuf);
+
+    impl FilesDir {
+        fn new(files: &[(&str, &str)]) -> Self {
+            let dir = std::env::temp_dir().join(format!("hedit-files-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&dir).unwrap();
+            for (name, content) in files {
+                std::fs::write(get_local_file_path(&dir, name), content).unwrap();
+            }
+            FilesDir(dir)
+        }
+    }
+
+    impl Drop for FilesDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Composes `content` with `files` as the local hosts files it may include
+    async fn compose(files: &[(&str, &str)], content: &str) -> (String, Vec<Diagnostic>) {
+        let files_dir = FilesDir::new(files);
+        let mut diagnostics = Vec::new();
+        let (parts, _) = collect_local_parts(&files_dir.0, content, &mut diagnostics).await;
+        let mut output = Vec::new();
+        write_composed(&files_dir.0, &parts, &mut diagnostics, &mut output)
+            .await
+            .unwrap();
+        (String::from_utf8(output).unwrap(), diagnostics)
+    }
+
+    fn codes(diagnostics: &[Diagnostic]) -> Vec<(Severity, DiagnosticCode)> {
+        diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.severity, diagnostic.code))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn rejects_duplicates_by_default() {
+        let content =
+            "10.0.0.1 a.test\n10.0.0.2 a.test\n::1 a.test\n10.0.0.3 xn--bcher-kva.test bücher.test";
+        let (output, diagnostics) = compose(&[], content).await;
+        assert_eq!(output, content.replace("bücher.test", "xn--bcher-kva.test"));
+        assert_eq!(
+            codes(&diagnostics),
+            [
+                (Severity::Error, DiagnosticCode::DuplicateHostname),
+                (Severity::Error, DiagnosticCode::DuplicateHostname),
+            ]
+        );
+        assert_eq!(diagnostics[0].line, 2);
+        assert_eq!(diagnostics[1].line, 4);
+    }
+
+    #[tokio::test]
+    async fn first_wins_keeps_the_first_occurrence() {
+        let (output, diagnostics) = compose(
+            &[],
+            "@duplicates(first-wins)\n10.0.0.1 a.test\n10.0.0.2 a.test b.test",
+        )
+        .await;
+        assert_eq!(
+            output,
+            "10.0.0.1 a.test\n# Duplicate dropped (first-wins): 10.0.0.2 a.test\n10.0.0.2 b.test"
+        );
+        assert_eq!(
+            codes(&diagnostics),
+            [(Severity::Warning, DiagnosticCode::DuplicateHostname)]
+        );
+    }
+
+    #[tokio::test]
+    async fn last_wins_keeps_the_last_occurrence() {
+        let (output, _) = compose(
+            &[("list", "10.0.0.9 a.test c.test")],
+            "@duplicates(last-wins)\n10.0.0.1 a.test b.test\n@local(list)\n10.0.0.2 b.test",
+        )
+        .await;
+        assert_eq!(
+            output,
+            "# Duplicate dropped (last-wins): 10.0.0.1 a.test b.test\n\
+             # Begin included local hosts file: list\n\
+             10.0.0.9 a.test c.test\n\
+             # End included local hosts file\n\
+             \n\
+             10.0.0.2 b.test"
+        );
+    }
+
+    #[tokio::test]
+    async fn prefer_inline_keeps_the_composed_files_own_entries() {
+        let (output, diagnostics) = compose(
+            &[("list", "10.0.0.9 a.test c.test\n10.0.0.8 c.test")],
+            "@duplicates(prefer-inline)\n@local(list)\n10.0.0.1 a.test",
+        )
+        .await;
+        assert_eq!(
+            output,
+            "# Begin included local hosts file: list\n\
+             # Duplicate dropped (prefer-inline): 10.0.0.9 a.test\n\
+             10.0.0.9 c.test\n\
+             # Duplicate dropped (prefer-inline): 10.0.0.8 c.test\n\
+             # End included local hosts file\n\
+             \n\
+             10.0.0.1 a.test"
+        );
+        // Overlapping lists are expected, only the file's own duplicates are pointed out
+        assert!(diagnostics.is_empty());
+    }
+
+    #[tokio::test]
+    async fn expands_local_includes_recursively() {
+        let (output, diagnostics) = compose(
+            &[
+                ("outer", "@local(inner)\n10.0.0.1 outer.test"),
+                ("inner", "10.0.0.2 inner.test"),
+            ],
+            "@local(outer)",
+        )
+        .await;
+        assert_eq!(
+            output,
+            "# Begin included local hosts file: outer\n\
+             # Begin included local hosts file: inner\n\
+             10.0.0.2 inner.test\n\
+             # End included local hosts file\n\
+             \n\
+             10.0.0.1 outer.test\n\
+             # End included local hosts file\n"
+        );
+        assert!(diagnostics.is_empty());
+    }
+
+    #[tokio::test]
+    async fn reports_include_cycles() {
+        let (output, diagnostics) = compose(
+            &[("a", "@local(b)\n10.0.0.1 a.test"), ("b", "@local(a)")],
+            "@local(a)",
+        )
+        .await;
+        assert_eq!(
+            codes(&diagnostics),
+            [(Severity::Error, DiagnosticCode::IncludeCycle)]
+        );
+        assert_eq!(
+            diagnostics[0].message,
+            "Include cycle detected: a -> b -> a"
+        );
+        assert_eq!(
+            diagnostics[0].source,
+            DiagnosticSource::Local("b".to_string())
+        );
+        // The include closing the cycle is left out, the rest is composed
+        assert_eq!(output.matches("10.0.0.1 a.test").count(), 1);
+
+        let (_, diagnostics) = compose(&[("a", "@local(a)")], "@local(a)").await;
+        assert_eq!(diagnostics[0].message, "Include cycle detected: a -> a");
+    }
+
+    #[tokio::test]
+    async fn limits_the_include_depth() {
+        let names: Vec<String> = (0..=MAX_INCLUDE_DEPTH).map(|i| format!("f{}", i)).collect();
+        let contents: Vec<String> = (0..=MAX_INCLUDE_DEPTH)
+            .map(|i| format!("@local(f{})\n10.0.0.{} f{}.test", i + 1, i, i))
+            .collect();
+        let files: Vec<(&str, &str)> = names
+            .iter()
+            .zip(&contents)
+            .map(|(name, content)| (name.as_str(), content.as_str()))
+            .collect();
+
+        let (output, diagnostics) = compose(&files, "@local(f0)").await;
+        assert_eq!(
+            codes(&diagnostics),
+            [(Severity::Error, DiagnosticCode::IncludeDepthExceeded)]
+        );
+        assert_eq!(
+            diagnostics[0].message,
+            format!(
+                "Maximum include depth of {} exceeded by 'f{}'",
+                MAX_INCLUDE_DEPTH, MAX_INCLUDE_DEPTH
+            )
+        );
+        assert_eq!(diagnostics[0].included_from.len(), MAX_INCLUDE_DEPTH);
+        assert!(output.contains(&format!("f{}.test", MAX_INCLUDE_DEPTH - 1)));
+        assert!(!output.contains(&format!("f{}.test", MAX_INCLUDE_DEPTH)));
+    }
+
+    #[test]
+    fn matches_exclude_patterns() {
+        let excludes = [
+            normalize_exclude("*.Ads.test"),
+            normalize_exclude("bücher.test"),
+        ];
+        assert_eq!(excludes, ["*.ads.test", "xn--bcher-kva.test"]);
+
+        assert!(is_excluded("x.ads.test", &excludes));
+        assert!(is_excluded("a.b.ADS.test", &excludes));
+        assert!(!is_excluded("ads.test", &excludes));
+        assert!(!is_excluded("badads.test", &excludes));
+        assert!(is_excluded("BÜCHER.test", &excludes));
+        assert!(is_excluded("xn--bcher-kva.test", &excludes));
+        assert!(!is_excluded("www.bücher.test", &excludes));
+    }
+
+    #[tokio::test]
+    async fn excludes_hostnames_from_included_files() {
+        let (output, diagnostics) = compose(
+            &[
+                (
+                    "list",
+                    "0.0.0.0 x.ads.test ok.test\n0.0.0.0 ads.test\n0.0.0.0 y.ads.test",
+                ),
+                ("wrapper", "@local(list)"),
+            ],
+            "@exclude(*.ads.test)\n@local(wrapper)\n10.0.0.1 own.ads.test",
+        )
+        .await;
+        assert_eq!(
+            output,
+            "# Begin included local hosts file: wrapper\n\
+             # Begin included local hosts file: list\n\
+             # Excluded: 0.0.0.0 x.ads.test\n\
+             0.0.0.0 ok.test\n\
+             0.0.0.0 ads.test\n\
+             # Excluded: 0.0.0.0 y.ads.test\n\
+             # End included local hosts file\n\
+             \n\
+             # End included local hosts file\n\
+             \n\
+             10.0.0.1 own.ads.test"
+        );
+        assert!(diagnostics.is_empty());
+    }
+
+    #[tokio::test]
+    async fn included_files_inherit_variables() {
+        let (output, diagnostics) = compose(
+            &[
+                ("dev", "@define(HOST=dev.test)\n${IP} ${HOST}"),
+                ("other", "${IP} other.test ${HOST}"),
+            ],
+            "@define(IP=10.0.0.5)\n@local(dev)\n@local(other)",
+        )
+        .await;
+        assert!(output.contains("\n10.0.0.5 dev.test\n"));
+        // Variables go down to included files, never up or across
+        assert!(output.contains("\n10.0.0.5 other.test ${HOST}\n"));
+        assert_eq!(
+            codes(&diagnostics),
+            [
+                (Severity::Error, DiagnosticCode::UndefinedVariable),
+                (Severity::Error, DiagnosticCode::InvalidHostname),
+            ]
+        );
+        assert_eq!(
+            diagnostics[0].source,
+            DiagnosticSource::Local("other".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn leaves_undefined_variables_to_the_including_file() {
+        let files_dir = FilesDir::new(&[]);
+        let content = "${IP} dev.test\n10.0.0.1 ${HOST}.test\n${IP} bad_host!";
+        let diagnostics = check_inline(&files_dir.0, content).await;
+        assert_eq!(
+            codes(&diagnostics),
+            [
+                (Severity::Warning, DiagnosticCode::UndefinedVariable),
+                (Severity::Warning, DiagnosticCode::UndefinedVariable),
+                (Severity::Warning, DiagnosticCode::UndefinedVariable),
+                (Severity::Error, DiagnosticCode::InvalidHostname),
+            ]
+        );
+        assert!(diagnostics[0]
+            .message
+            .ends_with("unless a file including this one defines it"));
+
+        // Composed on its own, nothing can define them
+        let (_, diagnostics) = compose(&[], content).await;
+        assert_eq!(
+            first_error(&diagnostics).map(|d| d.code),
+            Some(DiagnosticCode::UndefinedVariable)
+        );
+        assert!(codes(&diagnostics).contains(&(Severity::Error, DiagnosticCode::InvalidIp)));
+    }
 }
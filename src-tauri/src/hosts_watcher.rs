@@ -0,0 +1,152 @@
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hasher;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tauri::{command, AppHandle, Emitter, Manager};
+use tokio::fs;
+use tokio::sync::Mutex;
+use tokio::time::sleep;
+
+use crate::files;
+use crate::hosts_diff::{self, HostsDiff};
+use crate::hosts_installer::{self, SYSTEM_HOSTS_PATH};
+
+const WATCH_INTERVAL_SECS: u64 = 5;
+
+/// Event name constants
+const EVENT_SYSTEM_HOSTS_CHANGED: &str = "system-hosts-changed";
+
+/// Held while Hedit replaces the system hosts file, so the watcher never takes
+/// its own write for an external change
+static WRITE_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemHostsChange {
+    /// From what Hedit last installed to what the system hosts file is now
+    pub diff: HostsDiff,
+    /// RFC 3339
+    pub detected_at: String,
+}
+
+/// Copy of the hosts file Hedit last installed
+fn get_last_installed_path(app_handle: &AppHandle) -> Result<PathBuf, String> {
+    let dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| e.to_string())?;
+    Ok(dir.join("last-installed.hosts"))
+}
+
+fn content_hash(content: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(content);
+    hasher.finish()
+}
+
+async fn record_installed(app_handle: &AppHandle, source: &Path) -> Result<(), String> {
+    let path = get_last_installed_path(app_handle)?;
+    fs::copy(source, &path)
+        .await
+        .map_err(|e| format!("Error recording the installed hosts file: {}", e))?;
+    Ok(())
+}
+
+/// Installs `source` as the system hosts file and remembers it as what Hedit last wrote
+pub async fn install_and_record(app_handle: &AppHandle, source: &Path) -> Result<(), String> {
+    let _guard = WRITE_LOCK.lock().await;
+    hosts_installer::install_hosts_file(source).await?;
+    record_installed(app_handle, source).await
+}
+
+/// What the watcher knows from its previous checks
+#[derive(Default)]
+struct WatchState {
+    /// Modification time and size of the system hosts file when it was last read
+    seen: Option<(SystemTime, u64)>,
+    /// Hash of the external change last reported, so it is reported once
+    reported: Option<u64>,
+}
+
+async fn check_system_hosts(app_handle: &AppHandle, state: &mut WatchState) -> Result<(), String> {
+    let _guard = WRITE_LOCK.lock().await;
+
+    let metadata = fs::metadata(SYSTEM_HOSTS_PATH)
+        .await
+        .map_err(|e| format!("Error reading {}: {}", SYSTEM_HOSTS_PATH, e))?;
+    let stamp = (
+        metadata.modified().map_err(|e| e.to_string())?,
+        metadata.len(),
+    );
+    if state.seen == Some(stamp) {
+        return Ok(());
+    }
+    state.seen = Some(stamp);
+
+    let last_installed = match fs::read(get_last_installed_path(app_handle)?).await {
+        Ok(content) => content,
+        // Hedit has not written the system hosts file yet, nothing can drift from it
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => {
+            return Err(format!(
+                "Error reading the installed hosts file copy: {}",
+                e
+            ))
+        }
+    };
+    let system = fs::read(SYSTEM_HOSTS_PATH)
+        .await
+        .map_err(|e| format!("Error reading {}: {}", SYSTEM_HOSTS_PATH, e))?;
+
+    let hash = content_hash(&system);
+    if hash == content_hash(&last_installed) {
+        state.reported = None;
+        return Ok(());
+    }
+    if state.reported == Some(hash) {
+        return Ok(());
+    }
+    state.reported = Some(hash);
+
+    let change = SystemHostsChange {
+        diff: hosts_diff::diff_contents(
+            &String::from_utf8_lossy(&last_installed),
+            &String::from_utf8_lossy(&system),
+        ),
+        detected_at: chrono::Local::now().to_rfc3339(),
+    };
+    app_handle
+        .emit(EVENT_SYSTEM_HOSTS_CHANGED, change)
+        .map_err(|e| format!("Failed to emit {} event: {}", EVENT_SYSTEM_HOSTS_CHANGED, e))
+}
+
+/// Background task that reports changes made to the system hosts file outside of Hedit
+pub async fn watch_system_hosts(app: AppHandle) {
+    sleep(Duration::from_secs(10)).await; // Initial delay to ensure app is ready
+
+    let mut state = WatchState::default();
+    loop {
+        if let Err(e) = check_system_hosts(&app, &mut state).await {
+            eprintln!("Error checking the system hosts file for changes: {}", e);
+        }
+        sleep(Duration::from_secs(WATCH_INTERVAL_SECS)).await;
+    }
+}
+
+/// Writes the hosts file `file_id` to the system again, undoing external changes
+#[command]
+pub async fn reapply_hosts_file(
+    app_handle: tauri::AppHandle,
+    file_id: String,
+) -> Result<(), String> {
+    files::write_system_hosts_from_file(&app_handle, &format!("{}.hosts", file_id)).await
+}
+
+/// Accepts the system hosts file as it is now, ignoring external changes made so far
+#[command]
+pub async fn dismiss_system_hosts_changes(app_handle: tauri::AppHandle) -> Result<(), String> {
+    let _guard = WRITE_LOCK.lock().await;
+    record_installed(&app_handle, Path::new(SYSTEM_HOSTS_PATH)).await
+}
@@ -0,0 +1,124 @@
+use std::borrow::Cow;
+
+/// Punycode (`xn--`) form of `host`. ASCII hostnames and those that cannot be converted
+/// are returned as they are.
+pub fn to_ascii(host: &str) -> Cow<'_, str> {
+    if host.is_ascii() {
+        return Cow::Borrowed(host);
+    }
+    match idna::domain_to_ascii(host) {
+        Ok(ascii) => Cow::Owned(ascii),
+        Err(_) => Cow::Borrowed(host),
+    }
+}
+
+/// Unicode form of `host`, decoding its `xn--` labels
+pub fn to_unicode(host: &str) -> Cow<'_, str> {
+    if !host.to_ascii_lowercase().contains("xn--") {
+        return Cow::Borrowed(host);
+    }
+    match idna::domain_to_unicode(host) {
+        (unicode, Ok(())) => Cow::Owned(unicode),
+        (_, Err(_)) => Cow::Borrowed(host),
+    }
+}
+
+/// `host` followed by its other form, e.g. `bücher.de (xn--bcher-kva.de)`
+pub fn describe(host: &str) -> String {
+    let other = if host.is_ascii() {
+        to_unicode(host)
+    } else {
+        to_ascii(host)
+    };
+
+    if other == host {
+        host.to_string()
+    } else {
+        format!("{} ({})", host, other)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Script {
+    Latin,
+    Greek,
+    Cyrillic,
+    Armenian,
+    Hebrew,
+    Arabic,
+    Devanagari,
+    Thai,
+    Georgian,
+    /// Han, Hiragana and Katakana, which are commonly mixed with each other and with Latin
+    Cjk,
+    Hangul,
+}
+
+impl Script {
+    fn of(c: char) -> Option<Self> {
+        if c.is_ascii_alphabetic() {
+            return Some(Script::Latin);
+        }
+        match c as u32 {
+            0x00C0..=0x024F | 0x1E00..=0x1EFF => Some(Script::Latin),
+            0x0370..=0x03FF | 0x1F00..=0x1FFF => Some(Script::Greek),
+            0x0400..=0x052F => Some(Script::Cyrillic),
+            0x0530..=0x058F => Some(Script::Armenian),
+            0x0590..=0x05FF => Some(Script::Hebrew),
+            0x0600..=0x06FF | 0x0750..=0x077F => Some(Script::Arabic),
+            0x0900..=0x097F => Some(Script::Devanagari),
+            0x0E00..=0x0E7F => Some(Script::Thai),
+            0x10A0..=0x10FF => Some(Script::Georgian),
+            0x3040..=0x30FF | 0x3400..=0x4DBF | 0x4E00..=0x9FFF => Some(Script::Cjk),
+            0x1100..=0x11FF | 0xAC00..=0xD7AF => Some(Script::Hangul),
+            // Digits, hyphens and characters of other scripts
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Script::Latin => "Latin",
+            Script::Greek => "Greek",
+            Script::Cyrillic => "Cyrillic",
+            Script::Armenian => "Armenian",
+            Script::Hebrew => "Hebrew",
+            Script::Arabic => "Arabic",
+            Script::Devanagari => "Devanagari",
+            Script::Thai => "Thai",
+            Script::Georgian => "Georgian",
+            Script::Cjk => "CJK",
+            Script::Hangul => "Hangul",
+        }
+    }
+}
+
+/// First label of `host` that mixes letters of different scripts, like a Cyrillic `а`
+/// in an otherwise Latin name, with the names of the scripts involved.
+/// CJK and Hangul may be combined with each other and with Latin.
+pub fn mixed_script_label(host: &str) -> Option<(String, Vec<&'static str>)> {
+    let unicode = to_unicode(host);
+
+    unicode.split('.').find_map(|label| {
+        let mut scripts: Vec<Script> = Vec::new();
+        for script in label.chars().filter_map(Script::of) {
+            if !scripts.contains(&script) {
+                scripts.push(script);
+            }
+        }
+
+        let confusable = scripts
+            .iter()
+            .filter(|script| !matches!(script, Script::Cjk | Script::Hangul))
+            .count();
+        let is_mixed = confusable > 1
+            || (confusable == 1 && scripts.len() > 1 && !scripts.contains(&Script::Latin));
+
+        is_mixed.then(|| {
+            (
+                label.to_string(),
+                scripts.iter().map(Script::name).collect(),
+            )
+        })
+    })
+}
@@ -0,0 +1,88 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+/// Address of a hosts entry, with the zone of a scoped IPv6 address such as `fe80::1%eth0`
+pub struct ScopedIp<'a> {
+    pub addr: IpAddr,
+    pub zone: Option<&'a str>,
+}
+
+impl ScopedIp<'_> {
+    pub fn parse(text: &str) -> Option<ScopedIp<'_>> {
+        let Some((addr, zone)) = text.split_once('%') else {
+            return IpAddr::from_str(text)
+                .ok()
+                .map(|addr| ScopedIp { addr, zone: None });
+        };
+
+        let is_valid_zone = !zone.is_empty()
+            && zone
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_' | ':'));
+        if !is_valid_zone {
+            return None;
+        }
+
+        // Only IPv6 addresses have zones
+        Ipv6Addr::from_str(addr).ok().map(|addr| ScopedIp {
+            addr: IpAddr::V6(addr),
+            zone: Some(zone),
+        })
+    }
+
+    /// Whether the zone is meaningful for the address, i.e. it is link-local
+    pub fn is_link_local(&self) -> bool {
+        match self.addr {
+            IpAddr::V6(addr) => {
+                (addr.segments()[0] & 0xffc0) == 0xfe80
+                    || (addr.is_multicast() && (addr.segments()[0] & 0x000f) <= 2)
+            }
+            IpAddr::V4(addr) => addr.is_link_local(),
+        }
+    }
+}
+
+/// IPv6 notations of an IPv4 address, which resolvers may return as IPv4 or ignore
+pub enum EmbeddedIpv4 {
+    /// `::ffff:a.b.c.d`
+    Mapped(Ipv4Addr),
+    /// `::a.b.c.d`, deprecated by RFC 4291
+    Compatible(Ipv4Addr),
+}
+
+impl EmbeddedIpv4 {
+    pub fn of(addr: &Ipv6Addr) -> Option<Self> {
+        if let Some(ipv4) = addr.to_ipv4_mapped() {
+            return Some(EmbeddedIpv4::Mapped(ipv4));
+        }
+
+        let segments = addr.segments();
+        // `::` and `::1` are not IPv4 addresses
+        if segments[..6].iter().all(|&s| s == 0) && segments[6] != 0 {
+            return addr.to_ipv4().map(EmbeddedIpv4::Compatible);
+        }
+
+        None
+    }
+}
+
+/// Whether the interface named, or numbered, by `zone` exists on this machine
+#[cfg(unix)]
+pub fn interface_exists(zone: &str) -> bool {
+    if let Ok(index) = zone.parse::<u32>() {
+        let mut name = [0 as libc::c_char; libc::IF_NAMESIZE];
+        // SAFETY: `name` has room for the IF_NAMESIZE bytes `if_indextoname` may write
+        return unsafe { !libc::if_indextoname(index, name.as_mut_ptr()).is_null() };
+    }
+
+    let Ok(name) = std::ffi::CString::new(zone) else {
+        return false;
+    };
+    // SAFETY: `name` is a valid NUL-terminated string
+    unsafe { libc::if_nametoindex(name.as_ptr()) != 0 }
+}
+
+#[cfg(not(unix))]
+pub fn interface_exists(_zone: &str) -> bool {
+    true
+}
@@ -1,13 +1,32 @@
 use std::env;
+mod conditionals;
+mod diagnostics;
+mod expansion;
+mod exporter;
 mod files;
+mod formatter;
+mod hostname_explainer;
+mod hosts_backups;
+mod hosts_diff;
+mod hosts_document;
+mod hosts_import;
+mod hosts_installer;
 mod hosts_parser;
+mod hosts_watcher;
+mod idn;
+mod ip_address;
 mod license;
+mod list_formats;
+mod managed_block;
 mod menu;
+mod remote_cache;
 mod remote_hosts;
 mod settings_store;
 mod sync_remote_hosts;
 mod telemetry;
+mod temp_file;
 mod update_checker;
+mod variables;
 use std::fs::create_dir_all;
 use tauri::image::Image;
 use tauri::path::BaseDirectory;
@@ -71,6 +90,7 @@ pub fn run() {
             tauri::async_runtime::spawn(sync_remote_hosts::auto_update_hosts_periodically(
                 app.handle().clone(),
             ));
+            tauri::async_runtime::spawn(hosts_watcher::watch_system_hosts(app.handle().clone()));
 
             let resource_path = app
                 .path()
@@ -187,6 +207,19 @@ pub fn run() {
             license::activate,
             license::get_build_date_command,
             files::write_file,
+            files::get_file_diagnostics,
+            files::format_hosts_file,
+            hostname_explainer::explain_hostname,
+            list_formats::import_hosts_list,
+            exporter::export_hosts_file,
+            hosts_diff::diff_hosts,
+            hosts_backups::list_hosts_backups,
+            hosts_backups::read_hosts_backup,
+            hosts_backups::restore_hosts_backup,
+            managed_block::get_hosts_block_conflicts,
+            hosts_watcher::reapply_hosts_file,
+            hosts_watcher::dismiss_system_hosts_changes,
+            hosts_import::import_system_hosts,
             remote_hosts::fetch_remote_hosts_file,
             sync_remote_hosts::trigger_manual_sync,
             telemetry::send_telemetry_event,
@@ -0,0 +1,581 @@
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::net::IpAddr;
+use std::path::Path;
+use std::str::FromStr;
+use tauri::command;
+
+use crate::hosts_document::{HostsLine, LineKind};
+use crate::hosts_parser::is_valid_hostname;
+use crate::ip_address::ScopedIp;
+use crate::temp_file::LineReader;
+
+/// Address of the entries converted from blocking rules
+const BLOCKING_IP: &str = "0.0.0.0";
+
+/// How many significant lines at the start of a list are looked at to detect its format
+const DETECTION_SAMPLE: usize = 200;
+
+/// How many unconverted lines are listed in a report, the others are only counted
+const MAX_REPORTED_LINES: usize = 100;
+
+/// Syntax of a block or redirect list
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ListFormat {
+    /// `0.0.0.0 example.com`
+    Hosts,
+    /// Adblock Plus `||example.com^`
+    Adblock,
+    /// dnsmasq `address=/example.com/0.0.0.0`
+    Dnsmasq,
+    /// unbound `local-zone: "example.com" always_nxdomain` and `local-data: "example.com A 1.2.3.4"`
+    Unbound,
+    /// One domain per line
+    DomainList,
+}
+
+impl ListFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ListFormat::Hosts => "hosts",
+            ListFormat::Adblock => "Adblock Plus",
+            ListFormat::Dnsmasq => "dnsmasq",
+            ListFormat::Unbound => "unbound",
+            ListFormat::DomainList => "domain list",
+        }
+    }
+
+    /// Format a single line looks like, `None` for blank lines, comments and unknown lines
+    fn of_line(line: &str) -> Option<Self> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(['#', '!', '[']) {
+            return None;
+        }
+
+        // Before Adblock, as a hosts entry may end in a `## comment` that reads as element hiding
+        if is_hosts_entry(line) {
+            Some(ListFormat::Hosts)
+        } else if line.starts_with("||") || line.starts_with("@@") || line.contains("##") {
+            Some(ListFormat::Adblock)
+        } else if ["address=", "server=", "local="]
+            .iter()
+            .any(|prefix| line.starts_with(prefix))
+        {
+            Some(ListFormat::Dnsmasq)
+        } else if ["local-zone:", "local-data:", "server:"]
+            .iter()
+            .any(|prefix| line.starts_with(prefix))
+        {
+            Some(ListFormat::Unbound)
+        } else {
+            let host = line.split('#').next().unwrap_or_default().trim();
+            let host = host.strip_prefix("*.").unwrap_or(host);
+            (!host.contains(char::is_whitespace) && is_valid_hostname(host))
+                .then_some(ListFormat::DomainList)
+        }
+    }
+
+    /// Most common format among the first significant `lines`, `Hosts` if there are none
+    pub fn detect<'a>(lines: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut votes: Vec<(ListFormat, usize)> = Vec::new();
+        for format in lines
+            .into_iter()
+            .filter_map(ListFormat::of_line)
+            .take(DETECTION_SAMPLE)
+        {
+            match votes.iter_mut().find(|(f, _)| *f == format) {
+                Some((_, count)) => *count += 1,
+                None => votes.push((format, 1)),
+            }
+        }
+
+        // Ties go to the format seen first
+        let mut best = (ListFormat::Hosts, 0);
+        for vote in votes {
+            if vote.1 > best.1 {
+                best = vote;
+            }
+        }
+        best.0
+    }
+
+    /// Detects the format of the list at `path` from its first lines
+    pub fn detect_file(path: &Path) -> Result<Self, String> {
+        let mut reader = LineReader::open(path)?;
+        let mut sample = Vec::new();
+        while let Some((_, raw)) = reader.next_line()? {
+            if ListFormat::of_line(&raw).is_some() {
+                sample.push(raw);
+                if sample.len() == DETECTION_SAMPLE {
+                    break;
+                }
+            }
+        }
+        Ok(ListFormat::detect(sample.iter().map(String::as_str)))
+    }
+}
+
+/// Hosts form of one line of a list in `format`, or why it cannot be converted.
+/// Every line converts to exactly one line, so line numbers are kept.
+pub fn convert_line(format: ListFormat, raw: &str) -> Result<Cow<'_, str>, &'static str> {
+    let line = raw.trim();
+    // Adblock Plus element hiding rules start with `#`
+    let is_comment = line.starts_with('#') && format != ListFormat::Adblock;
+    if line.is_empty() || is_comment {
+        return Ok(Cow::Borrowed(raw));
+    }
+
+    match format {
+        ListFormat::Hosts => Ok(Cow::Borrowed(raw)),
+        ListFormat::Adblock => convert_adblock(line),
+        ListFormat::Dnsmasq => convert_dnsmasq(line),
+        ListFormat::Unbound => convert_unbound(line),
+        ListFormat::DomainList => convert_domain(line),
+    }
+}
+
+/// Whether `line` is a hosts entry with a valid address and at least one hostname
+fn is_hosts_entry(line: &str) -> bool {
+    match HostsLine::parse(0, line).kind {
+        LineKind::Entry(entry) => {
+            !entry.hostnames.is_empty() && ScopedIp::parse(entry.ip.text).is_some()
+        }
+        _ => false,
+    }
+}
+
+/// `domain` without a trailing root dot, if it is a valid hostname
+fn domain(text: &str) -> Option<&str> {
+    let text = text.strip_suffix('.').unwrap_or(text);
+    is_valid_hostname(text).then_some(text)
+}
+
+fn blocking_entry(domains: &[&str]) -> Cow<'static, str> {
+    Cow::Owned(format!("{} {}", BLOCKING_IP, domains.join(" ")))
+}
+
+fn convert_adblock(line: &str) -> Result<Cow<'_, str>, &'static str> {
+    if let Some(comment) = line.strip_prefix('!') {
+        return Ok(Cow::Owned(format!("#{}", comment)));
+    }
+    if line.starts_with('[') {
+        // `[Adblock Plus 2.0]` header
+        return Ok(Cow::Owned(format!("# {}", line)));
+    }
+    if line.starts_with("@@") {
+        return Err("exception rule");
+    }
+    if line.contains("##") || line.contains("#@#") || line.contains("#?#") {
+        return Err("element hiding rule");
+    }
+
+    let Some(rule) = line.strip_prefix("||") else {
+        return Err("not a domain rule");
+    };
+    let (rule, options) = rule.split_once('$').unwrap_or((rule, ""));
+    let has_other_options = options
+        .split(',')
+        .any(|option| !matches!(option, "" | "important" | "all" | "document" | "doc"));
+    if has_other_options {
+        return Err("rule with options that a hosts file cannot express");
+    }
+
+    let host = rule.strip_suffix('^').unwrap_or(rule);
+    match domain(host) {
+        Some(host) => Ok(blocking_entry(&[host])),
+        None => Err("rule with a path or wildcard"),
+    }
+}
+
+fn convert_dnsmasq(line: &str) -> Result<Cow<'_, str>, &'static str> {
+    let Some(rule) = line.strip_prefix("address=/") else {
+        return if line.starts_with("server=") || line.starts_with("local=") {
+            Err("forwarding rule")
+        } else {
+            Err("unsupported dnsmasq option")
+        };
+    };
+
+    // `address=/a.com/b.com/ip`, where an empty address or `#` blocks the domains
+    let mut fields: Vec<&str> = rule.split('/').collect();
+    let address = fields.pop().unwrap_or_default();
+    let mut domains = Vec::new();
+    for field in fields {
+        match domain(field.trim_start_matches('.')) {
+            Some(host) => domains.push(host),
+            None => return Err("invalid domain"),
+        }
+    }
+    if domains.is_empty() {
+        return Err("rule for every domain");
+    }
+
+    if address.is_empty() || address == "#" {
+        return Ok(blocking_entry(&domains));
+    }
+    match IpAddr::from_str(address) {
+        Ok(ip) => Ok(Cow::Owned(format!("{} {}", ip, domains.join(" ")))),
+        Err(_) => Err("invalid address"),
+    }
+}
+
+fn convert_unbound(line: &str) -> Result<Cow<'_, str>, &'static str> {
+    if line == "server:" {
+        return Ok(Cow::Owned(format!("# {}", line)));
+    }
+
+    if let Some(zone) = line.strip_prefix("local-zone:") {
+        let mut fields = zone.split_whitespace();
+        let host = fields.next().unwrap_or_default().trim_matches('"');
+        let zone_type = fields.next().unwrap_or_default();
+        let is_blocking = matches!(
+            zone_type,
+            "always_nxdomain"
+                | "always_refuse"
+                | "always_null"
+                | "refuse"
+                | "static"
+                | "deny"
+                | "inform_deny"
+        );
+        if !is_blocking {
+            return Err("local zone that does not block");
+        }
+        return match domain(host) {
+            Some(host) => Ok(blocking_entry(&[host])),
+            None => Err("invalid domain"),
+        };
+    }
+
+    if let Some(data) = line.strip_prefix("local-data:") {
+        // `"name [TTL] [IN] A|AAAA address"`
+        let fields: Vec<&str> = data
+            .trim()
+            .trim_matches('"')
+            .split_whitespace()
+            .filter(|field| *field != "IN" && field.parse::<u32>().is_err())
+            .collect();
+        let [name, record_type, address] = fields[..] else {
+            return Err("unsupported local data");
+        };
+        if !matches!(record_type, "A" | "AAAA") {
+            return Err("record type other than A or AAAA");
+        }
+        let (Some(host), Ok(ip)) = (domain(name), IpAddr::from_str(address)) else {
+            return Err("invalid local data");
+        };
+        return Ok(Cow::Owned(format!("{} {}", ip, host)));
+    }
+
+    Err("unsupported unbound option")
+}
+
+fn convert_domain(line: &str) -> Result<Cow<'_, str>, &'static str> {
+    // Domain lists are sometimes mixed with hosts entries
+    if is_hosts_entry(line) {
+        return Ok(Cow::Borrowed(line));
+    }
+
+    let (host, comment) = match line.split_once('#') {
+        Some((host, comment)) => (host.trim(), Some(comment)),
+        None => (line, None),
+    };
+    if host.starts_with("*.") {
+        return Err("wildcard domain");
+    }
+    let Some(host) = domain(host) else {
+        return Err("not a domain");
+    };
+
+    Ok(Cow::Owned(match comment {
+        Some(comment) => format!("{} {} #{}", BLOCKING_IP, host, comment),
+        None => format!("{} {}", BLOCKING_IP, host),
+    }))
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnconvertedLine {
+    /// 1-based line number
+    pub line: usize,
+    pub text: String,
+    pub reason: &'static str,
+}
+
+/// Outcome of converting a list to hosts syntax
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConversionReport {
+    pub format: ListFormat,
+    pub unconverted_count: usize,
+    /// The first unconverted lines
+    pub unconverted: Vec<UnconvertedLine>,
+}
+
+impl ConversionReport {
+    /// e.g. `Converted from Adblock Plus format, 2 lines could not be converted (line 3: exception rule, ...)`
+    pub fn summary(&self) -> String {
+        let mut summary = format!("Converted from {} format", self.format.name());
+        if self.unconverted_count > 0 {
+            let examples: Vec<String> = self
+                .unconverted
+                .iter()
+                .take(3)
+                .map(|line| format!("line {}: {}", line.line, line.reason))
+                .collect();
+            summary.push_str(&format!(
+                ", {} line{} could not be converted ({}{})",
+                self.unconverted_count,
+                if self.unconverted_count == 1 { "" } else { "s" },
+                examples.join(", "),
+                if self.unconverted_count > examples.len() {
+                    ", ..."
+                } else {
+                    ""
+                }
+            ));
+        }
+        summary
+    }
+}
+
+/// Converts a list line by line, keeping track of the lines it could not convert
+pub struct Converter {
+    report: ConversionReport,
+}
+
+impl Converter {
+    pub fn new(format: ListFormat) -> Self {
+        Converter {
+            report: ConversionReport {
+                format,
+                unconverted_count: 0,
+                unconverted: Vec::new(),
+            },
+        }
+    }
+
+    /// Hosts form of line `number`. Lines that cannot be converted are commented out.
+    pub fn convert<'a>(&mut self, number: usize, raw: &'a str) -> Cow<'a, str> {
+        match convert_line(self.report.format, raw) {
+            Ok(line) => line,
+            Err(reason) => {
+                self.report.unconverted_count += 1;
+                if self.report.unconverted.len() < MAX_REPORTED_LINES {
+                    self.report.unconverted.push(UnconvertedLine {
+                        line: number,
+                        text: raw.to_string(),
+                        reason,
+                    });
+                }
+                Cow::Owned(unconverted_comment(raw))
+            }
+        }
+    }
+
+    pub fn report(self) -> ConversionReport {
+        self.report
+    }
+}
+
+/// How a line that cannot be converted is kept in the hosts file
+pub fn unconverted_comment(raw: &str) -> String {
+    format!("# Unconverted: {}", raw.trim())
+}
+
+/// Converts the list at `path` line by line, passing every converted line to `f`
+pub fn convert_file(
+    path: &Path,
+    format: ListFormat,
+    mut f: impl FnMut(&str) -> Result<(), String>,
+) -> Result<ConversionReport, String> {
+    let mut converter = Converter::new(format);
+    let mut reader = LineReader::open(path)?;
+    while let Some((number, raw)) = reader.next_line()? {
+        f(&converter.convert(number, &raw))?;
+    }
+    Ok(converter.report())
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportedList {
+    /// The list in hosts syntax
+    pub content: String,
+    pub report: ConversionReport,
+}
+
+/// Reads the list at `path` for import as a local hosts file, converting it to hosts syntax.
+/// The format is detected unless `format` is given.
+#[command]
+pub fn import_hosts_list(path: String, format: Option<ListFormat>) -> Result<ImportedList, String> {
+    let path = Path::new(&path);
+    let format = match format {
+        Some(format) => format,
+        None => ListFormat::detect_file(path)?,
+    };
+
+    let mut lines = Vec::new();
+    let report = convert_file(path, format, |line| {
+        lines.push(line.to_string());
+        Ok(())
+    })?;
+    Ok(ImportedList {
+        content: lines.join("\n"),
+        report,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert(format: ListFormat, raw: &str) -> Result<String, &'static str> {
+        convert_line(format, raw).map(Cow::into_owned)
+    }
+
+    #[test]
+    fn converts_adblock_rules() {
+        let format = ListFormat::Adblock;
+        assert_eq!(
+            convert(format, "||ads.example.com^"),
+            Ok("0.0.0.0 ads.example.com".into())
+        );
+        assert_eq!(
+            convert(format, "||ads.example.com^$important"),
+            Ok("0.0.0.0 ads.example.com".into())
+        );
+        assert_eq!(convert(format, "! Title: list"), Ok("# Title: list".into()));
+        assert_eq!(
+            convert(format, "[Adblock Plus 2.0]"),
+            Ok("# [Adblock Plus 2.0]".into())
+        );
+        assert_eq!(
+            convert(format, "@@||good.example.com^"),
+            Err("exception rule")
+        );
+        assert_eq!(
+            convert(format, "example.com##.banner"),
+            Err("element hiding rule")
+        );
+        assert_eq!(convert(format, "##.banner"), Err("element hiding rule"));
+        assert!(convert(format, "||example.com^$third-party").is_err());
+        assert!(convert(format, "||example.com/ads^").is_err());
+    }
+
+    #[test]
+    fn converts_dnsmasq_rules() {
+        let format = ListFormat::Dnsmasq;
+        assert_eq!(
+            convert(format, "address=/ads.example.com/0.0.0.0"),
+            Ok("0.0.0.0 ads.example.com".into())
+        );
+        assert_eq!(
+            convert(format, "address=/a.example.com/b.example.com/#"),
+            Ok("0.0.0.0 a.example.com b.example.com".into())
+        );
+        assert_eq!(
+            convert(format, "address=/.local.test/10.0.0.1"),
+            Ok("10.0.0.1 local.test".into())
+        );
+        assert_eq!(convert(format, "address=/#/0.0.0.0"), Err("invalid domain"));
+        assert_eq!(
+            convert(format, "server=/example.com/1.1.1.1"),
+            Err("forwarding rule")
+        );
+        assert_eq!(
+            convert(format, "address=/example.com/nope"),
+            Err("invalid address")
+        );
+    }
+
+    #[test]
+    fn converts_unbound_rules() {
+        let format = ListFormat::Unbound;
+        assert_eq!(
+            convert(format, "local-zone: \"ads.example.com\" always_nxdomain"),
+            Ok("0.0.0.0 ads.example.com".into())
+        );
+        assert_eq!(
+            convert(format, "local-data: \"host.test. 3600 IN A 10.0.0.1\""),
+            Ok("10.0.0.1 host.test".into())
+        );
+        assert_eq!(
+            convert(format, "local-data: \"host.test AAAA ::1\""),
+            Ok("::1 host.test".into())
+        );
+        assert_eq!(convert(format, "server:"), Ok("# server:".into()));
+        assert_eq!(
+            convert(format, "local-zone: \"example.com\" transparent"),
+            Err("local zone that does not block")
+        );
+        assert_eq!(
+            convert(format, "local-data: \"host.test MX mail.test\""),
+            Err("record type other than A or AAAA")
+        );
+        assert_eq!(
+            convert(format, "local-data: \"host.test\""),
+            Err("unsupported local data")
+        );
+    }
+
+    #[test]
+    fn converts_domain_lists() {
+        let format = ListFormat::DomainList;
+        assert_eq!(
+            convert(format, "ads.example.com"),
+            Ok("0.0.0.0 ads.example.com".into())
+        );
+        assert_eq!(
+            convert(format, "ads.example.com # tracker"),
+            Ok("0.0.0.0 ads.example.com # tracker".into())
+        );
+        assert_eq!(
+            convert(format, "127.0.0.1 host.test"),
+            Ok("127.0.0.1 host.test".into())
+        );
+        assert_eq!(convert(format, "*.example.com"), Err("wildcard domain"));
+        assert_eq!(convert(format, "not a domain"), Err("not a domain"));
+    }
+
+    #[test]
+    fn keeps_blank_lines_and_comments() {
+        for format in [
+            ListFormat::Hosts,
+            ListFormat::Dnsmasq,
+            ListFormat::DomainList,
+        ] {
+            assert_eq!(convert(format, ""), Ok("".into()));
+            assert_eq!(convert(format, "  # note"), Ok("  # note".into()));
+        }
+        assert_eq!(
+            convert(ListFormat::Hosts, "anything goes"),
+            Ok("anything goes".into())
+        );
+    }
+
+    #[test]
+    fn detects_formats() {
+        let detect = |content: &str| ListFormat::detect(content.lines());
+        assert_eq!(
+            detect("! comment\n||a.test^\n||b.test^"),
+            ListFormat::Adblock
+        );
+        assert_eq!(detect("address=/a.test/0.0.0.0"), ListFormat::Dnsmasq);
+        assert_eq!(
+            detect("server:\nlocal-zone: \"a.test\" refuse"),
+            ListFormat::Unbound
+        );
+        assert_eq!(
+            detect("# list\na.test\nb.test\n0.0.0.0 c.test"),
+            ListFormat::DomainList
+        );
+        assert_eq!(detect("0.0.0.0 a.test\n0.0.0.0 b.test"), ListFormat::Hosts);
+        assert_eq!(detect("# only comments"), ListFormat::Hosts);
+        assert_eq!(
+            detect("0.0.0.0 a.test ## tracker\n0.0.0.0 b.test ## x\n0.0.0.0 c.test"),
+            ListFormat::Hosts
+        );
+    }
+}
@@ -0,0 +1,321 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
+use tauri::{command, AppHandle, Emitter};
+use tokio::fs;
+
+use crate::files::GENERATED_HEADER;
+use crate::hosts_diff::normalize_ip;
+use crate::hosts_document::HostsDocument;
+use crate::hosts_installer::SYSTEM_HOSTS_PATH;
+use crate::idn;
+
+pub const BEGIN_MARKER: &str = "# BEGIN HEDIT";
+pub const END_MARKER: &str = "# END HEDIT";
+
+/// Event name constants
+const EVENT_BLOCK_CONFLICTS: &str = "hosts-block-conflicts";
+
+/// The system hosts file split around the block Hedit owns
+struct Regions<'a> {
+    before: &'a str,
+    /// Between the markers, without them
+    block: Option<&'a str>,
+    after: &'a str,
+}
+
+impl<'a> Regions<'a> {
+    fn split(content: &'a str) -> Result<Self, String> {
+        let mut begin: Option<(usize, usize, usize)> = None;
+        let mut end: Option<(usize, usize)> = None;
+        let mut offset = 0;
+
+        for (index, line) in content.split_inclusive('\n').enumerate() {
+            let number = index + 1;
+            let next = offset + line.len();
+            match line.trim() {
+                BEGIN_MARKER => match (begin, end) {
+                    (None, _) => begin = Some((number, offset, next)),
+                    (Some((first, _, _)), _) => {
+                        return Err(format!(
+                            "{} has more than one '{}' block, on lines {} and {}",
+                            SYSTEM_HOSTS_PATH, BEGIN_MARKER, first, number
+                        ))
+                    }
+                },
+                END_MARKER => match (begin, end) {
+                    (Some(_), None) => end = Some((offset, next)),
+                    _ => {
+                        return Err(format!(
+                            "'{}' on line {} of {} has no '{}' before it",
+                            END_MARKER, number, SYSTEM_HOSTS_PATH, BEGIN_MARKER
+                        ))
+                    }
+                },
+                _ => {}
+            }
+            offset = next;
+        }
+
+        match (begin, end) {
+            (Some((_, begin_start, begin_end)), Some((end_start, end_end))) => Ok(Regions {
+                before: &content[..begin_start],
+                block: Some(&content[begin_end..end_start]),
+                after: &content[end_end..],
+            }),
+            (Some((number, _, _)), None) => Err(format!(
+                "'{}' on line {} of {} is never closed with '{}'",
+                BEGIN_MARKER, number, SYSTEM_HOSTS_PATH, END_MARKER
+            )),
+            // Written by Hedit before it kept to a block, nothing in it belongs to anyone else
+            _ if content.starts_with(GENERATED_HEADER) => Ok(Regions {
+                before: "",
+                block: None,
+                after: "",
+            }),
+            // The block is added at the end, so existing entries keep taking precedence
+            _ => Ok(Regions {
+                before: content,
+                block: None,
+                after: "",
+            }),
+        }
+    }
+}
+
+/// Writes `regions` with the content of `body` between the markers
+fn write_with_block(
+    regions: &Regions<'_>,
+    body: &mut impl Read,
+    writer: &mut impl Write,
+) -> std::io::Result<()> {
+    writer.write_all(regions.before.as_bytes())?;
+    if !regions.before.is_empty() && !regions.before.ends_with('\n') {
+        writer.write_all(b"\n")?;
+    }
+    writeln!(writer, "{}", BEGIN_MARKER)?;
+
+    // The end marker needs a line of its own, even when the body has no final line feed
+    let mut buffer = [0; 8192];
+    let mut last = b'\n';
+    loop {
+        let read = body.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        writer.write_all(&buffer[..read])?;
+        last = buffer[read - 1];
+    }
+    if last != b'\n' {
+        writer.write_all(b"\n")?;
+    }
+
+    writeln!(writer, "{}", END_MARKER)?;
+    writer.write_all(regions.after.as_bytes())?;
+    writer.flush()
+}
+
+/// Writes the installed hosts file with its Hedit block replaced by the content of `composed`,
+/// leaving everything outside the markers as it is
+pub async fn wrap_in_system_hosts(composed: &Path, output: File) -> Result<(), String> {
+    let system = match fs::read_to_string(SYSTEM_HOSTS_PATH).await {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(format!("Error reading {}: {}", SYSTEM_HOSTS_PATH, e)),
+    };
+    let regions = Regions::split(&system)?;
+
+    File::open(composed)
+        .and_then(|mut body| write_with_block(&regions, &mut body, &mut BufWriter::new(output)))
+        .map_err(|e| format!("Error writing hosts file: {}", e))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictWinner {
+    /// The entry outside the block comes first
+    System,
+    Hedit,
+}
+
+/// A hostname that the Hedit block and the rest of the file resolve to different addresses
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockConflict {
+    pub hostname: String,
+    pub is_ipv6: bool,
+    pub system_ip: String,
+    /// 1-based line number in the system hosts file
+    pub system_line: usize,
+    pub hedit_ip: String,
+    pub hedit_line: usize,
+    pub winner: ConflictWinner,
+}
+
+/// First entry per lowercase punycode hostname and address family: the address and line
+type Resolved = BTreeMap<(String, bool), (String, usize)>;
+
+fn find_conflicts(content: &str) -> Result<Vec<BlockConflict>, String> {
+    let regions = Regions::split(content)?;
+    let Some(block) = regions.block else {
+        return Ok(Vec::new());
+    };
+    // Line numbers of the block start after the lines before it and the marker
+    let block_start = regions.before.matches('\n').count() + 1;
+    let block_end = block_start + block.matches('\n').count() + 1;
+
+    let mut system = Resolved::new();
+    let mut hedit = Resolved::new();
+    let document = HostsDocument::parse(content);
+    for (line, entry) in document.entries() {
+        let resolved = if line.number > block_start && line.number < block_end {
+            &mut hedit
+        } else {
+            &mut system
+        };
+        let ip = normalize_ip(entry.ip.text);
+        let is_ipv6 = entry.ip.text.contains(':');
+        for host in &entry.hostnames {
+            let key = (idn::to_ascii(host.text).to_ascii_lowercase(), is_ipv6);
+            resolved
+                .entry(key)
+                .or_insert_with(|| (ip.clone(), line.number));
+        }
+    }
+
+    let mut conflicts = Vec::new();
+    for (key, (system_ip, system_line)) in system {
+        let Some((hedit_ip, hedit_line)) = hedit.remove(&key) else {
+            continue;
+        };
+        if system_ip == hedit_ip {
+            continue;
+        }
+        conflicts.push(BlockConflict {
+            hostname: key.0,
+            is_ipv6: key.1,
+            winner: if system_line < hedit_line {
+                ConflictWinner::System
+            } else {
+                ConflictWinner::Hedit
+            },
+            system_ip,
+            system_line,
+            hedit_ip,
+            hedit_line,
+        });
+    }
+    Ok(conflicts)
+}
+
+/// Hostnames that entries outside the Hedit block of the installed hosts file
+/// resolve differently than the block itself
+#[command]
+pub async fn get_hosts_block_conflicts() -> Result<Vec<BlockConflict>, String> {
+    let content = fs::read_to_string(SYSTEM_HOSTS_PATH)
+        .await
+        .map_err(|e| format!("Error reading {}: {}", SYSTEM_HOSTS_PATH, e))?;
+    find_conflicts(&content)
+}
+
+/// Tells the frontend about conflicts in the hosts file that was just installed
+pub async fn report_conflicts(app_handle: &AppHandle) {
+    match get_hosts_block_conflicts().await {
+        Ok(conflicts) => {
+            if let Err(e) = app_handle.emit(EVENT_BLOCK_CONFLICTS, conflicts) {
+                eprintln!("Failed to emit {} event: {}", EVENT_BLOCK_CONFLICTS, e);
+            }
+        }
+        Err(e) => eprintln!("Error checking hosts block conflicts: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wrap(system: &str, body: &str) -> String {
+        let regions = Regions::split(system).unwrap();
+        let mut output = Vec::new();
+        write_with_block(&regions, &mut body.as_bytes(), &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn splits_around_the_block() {
+        let content = "127.0.0.1 localhost\n# BEGIN HEDIT\n10.0.0.1 a\n# END HEDIT\n10.0.0.2 b\n";
+        let regions = Regions::split(content).unwrap();
+        assert_eq!(regions.before, "127.0.0.1 localhost\n");
+        assert_eq!(regions.block, Some("10.0.0.1 a\n"));
+        assert_eq!(regions.after, "10.0.0.2 b\n");
+    }
+
+    #[test]
+    fn splits_files_without_a_block() {
+        let regions = Regions::split("127.0.0.1 localhost\n").unwrap();
+        assert_eq!(regions.before, "127.0.0.1 localhost\n");
+        assert_eq!(regions.block, None);
+
+        // A file Hedit wrote whole is replaced whole
+        let generated = format!("{}\n10.0.0.1 a\n", GENERATED_HEADER);
+        let regions = Regions::split(&generated).unwrap();
+        assert_eq!((regions.before, regions.after), ("", ""));
+    }
+
+    #[test]
+    fn rejects_broken_markers() {
+        for content in [
+            "# END HEDIT\n",
+            "# BEGIN HEDIT\n10.0.0.1 a\n",
+            "# BEGIN HEDIT\n# BEGIN HEDIT\n# END HEDIT\n",
+            "# BEGIN HEDIT\n# END HEDIT\n# BEGIN HEDIT\n# END HEDIT\n",
+            "# BEGIN HEDIT\n# END HEDIT\n# END HEDIT\n",
+        ] {
+            assert!(Regions::split(content).is_err(), "{:?}", content);
+        }
+    }
+
+    #[test]
+    fn replaces_the_block_only() {
+        let system = "127.0.0.1 localhost\n# BEGIN HEDIT\nold\n# END HEDIT\n10.0.0.2 b";
+        assert_eq!(
+            wrap(system, "10.0.0.1 a\n"),
+            "127.0.0.1 localhost\n# BEGIN HEDIT\n10.0.0.1 a\n# END HEDIT\n10.0.0.2 b"
+        );
+        assert_eq!(
+            wrap("127.0.0.1 localhost", "10.0.0.1 a\n"),
+            "127.0.0.1 localhost\n# BEGIN HEDIT\n10.0.0.1 a\n# END HEDIT\n"
+        );
+    }
+
+    #[test]
+    fn keeps_the_end_marker_on_its_own_line() {
+        let written = wrap("", "127.0.0.1 a");
+        assert_eq!(written, "# BEGIN HEDIT\n127.0.0.1 a\n# END HEDIT\n");
+        assert_eq!(
+            Regions::split(&written).unwrap().block,
+            Some("127.0.0.1 a\n")
+        );
+
+        assert_eq!(wrap("", ""), "# BEGIN HEDIT\n# END HEDIT\n");
+    }
+
+    #[test]
+    fn finds_conflicts_with_the_rest_of_the_file() {
+        let content = "10.0.0.1 a.test\n# BEGIN HEDIT\n10.0.0.2 a.test b.test\n::1 c.test\n# END HEDIT\n10.0.0.3 b.test\n::0001 c.test\n";
+        let conflicts: Vec<(String, usize, usize, ConflictWinner)> = find_conflicts(content)
+            .unwrap()
+            .into_iter()
+            .map(|c| (c.hostname, c.system_line, c.hedit_line, c.winner))
+            .collect();
+        assert_eq!(
+            conflicts,
+            [
+                ("a.test".to_string(), 1, 3, ConflictWinner::System),
+                ("b.test".to_string(), 6, 3, ConflictWinner::Hedit),
+            ]
+        );
+    }
+}
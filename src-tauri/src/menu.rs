@@ -54,6 +54,11 @@ pub fn get_menu<R: Runtime>(app_handle: &AppHandle<R>) -> tauri::Result<Menu<R>>
             &MenuItemBuilder::with_id("new_file".to_string(), "New File")
                 .accelerator("CmdOrCtrl+n")
                 .build(app_handle)?,
+            &MenuItemBuilder::with_id(
+                "import_system_hosts".to_string(),
+                "Import System Hosts File",
+            )
+            .build(app_handle)?,
             &MenuItemBuilder::with_id("activate_file".to_string(), "Activate File")
                 .accelerator("CmdOrCtrl+Shift+a")
                 .build(app_handle)?,
@@ -165,6 +170,11 @@ pub fn handle_menu_event(app_handle: &tauri::AppHandle, event: &MenuEvent) {
                 eprintln!("Failed to emit new_file event: {}", e);
             }
         }
+        "import_system_hosts" => {
+            if let Err(e) = app_handle.emit("import_system_hosts", true) {
+                eprintln!("Failed to emit import_system_hosts event: {}", e);
+            }
+        }
         "activate_file" => {
             if let Err(e) = app_handle.emit("activate_file", true) {
                 eprintln!("Failed to emit activate_file event: {}", e);
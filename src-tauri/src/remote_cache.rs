@@ -0,0 +1,325 @@
+use chrono::{DateTime, Local};
+use reqwest::header::{
+    HeaderMap, HeaderValue, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tauri::{AppHandle, Manager};
+use tokio::fs;
+use tokio::sync::Semaphore;
+
+use crate::diagnostics::DiagnosticSource;
+use crate::hosts_document::HostsDocument;
+use crate::remote_hosts::{self, FetchError};
+use crate::variables::{self, Variables};
+
+/// Validators of a cached response, stored next to its body
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CacheEntry {
+    url: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    /// RFC 3339 time the body was downloaded
+    fetched_at: String,
+}
+
+/// A remote hosts file stored in the cache
+#[derive(Debug, Clone)]
+pub struct CachedFile {
+    /// Unmodified response body
+    pub path: PathBuf,
+    pub fetched_at: DateTime<Local>,
+    /// Why the server could not be asked whether the copy is still current
+    pub fetch_error: Option<String>,
+}
+
+impl CachedFile {
+    /// e.g. `cache age: 2h 5m`, or `cache age: 2h 5m, offline` when the copy was not revalidated
+    pub fn describe_age(&self) -> String {
+        let age = format_age(Local::now().signed_duration_since(self.fetched_at));
+        match self.fetch_error {
+            Some(_) => format!("cache age: {}, offline", age),
+            None => format!("cache age: {}", age),
+        }
+    }
+}
+
+fn format_age(age: chrono::TimeDelta) -> String {
+    let seconds = age.num_seconds().max(0);
+    match seconds {
+        0..60 => format!("{}s", seconds),
+        60..3600 => format!("{}m", seconds / 60),
+        3600..86400 => format!("{}h {}m", seconds / 3600, seconds % 3600 / 60),
+        _ => format!("{}d {}h", seconds / 86400, seconds % 86400 / 3600),
+    }
+}
+
+fn get_cache_dir(app_handle: &AppHandle) -> PathBuf {
+    let dir = app_handle.path().app_data_dir().unwrap();
+    dir.join("cache").join("remote")
+}
+
+/// File name stem of the cached copy of `url`, a 64-bit FNV-1a hash that is stable
+/// across builds. Collisions are detected by comparing the URL stored in the entry.
+fn cache_key(url: &str) -> String {
+    let hash = url.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{:016x}", hash)
+}
+
+async fn read_entry(path: &Path, url: &str) -> Option<CacheEntry> {
+    let content = fs::read_to_string(path).await.ok()?;
+    serde_json::from_str::<CacheEntry>(&content)
+        .ok()
+        .filter(|entry| entry.url == url)
+}
+
+fn header_text(headers: &HeaderMap, name: reqwest::header::HeaderName) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+/// Returns the cached copy of the remote hosts file at `url`, downloading it if it is
+/// missing or has changed. The request is conditional on the ETag and Last-Modified of
+/// the cached copy. If the server cannot be reached, the cached copy is used as it is.
+/// An error response is not covered by the cache, so a list that was taken down is noticed.
+pub async fn fetch(app_handle: &AppHandle, url: &str) -> Result<CachedFile, String> {
+    let dir = get_cache_dir(app_handle);
+    let key = cache_key(url);
+    let body_path = dir.join(format!("{}.hosts", key));
+    let entry_path = dir.join(format!("{}.json", key));
+
+    let cached = match read_entry(&entry_path, url).await {
+        Some(entry) if fs::metadata(&body_path).await.is_ok() => {
+            DateTime::parse_from_rfc3339(&entry.fetched_at)
+                .ok()
+                .map(|fetched_at| (entry, fetched_at.with_timezone(&Local)))
+        }
+        _ => None,
+    };
+
+    let mut headers = HeaderMap::new();
+    if let Some((entry, _)) = &cached {
+        let validators = [
+            (IF_NONE_MATCH, &entry.etag),
+            (IF_MODIFIED_SINCE, &entry.last_modified),
+        ];
+        for (name, value) in validators {
+            if let Some(value) = value.as_deref().and_then(|v| HeaderValue::from_str(v).ok()) {
+                headers.insert(name, value);
+            }
+        }
+    }
+
+    let result = download(app_handle, url, headers, &dir, &key).await;
+    match (result, cached) {
+        (Ok(Some(entry)), _) => {
+            let content = serde_json::to_string(&entry)
+                .map_err(|e| format!("Error serializing cache entry: {}", e))?;
+            let partial_path = dir.join(format!("{}.json.{}.part", key, uuid::Uuid::new_v4()));
+            let written = match fs::write(&partial_path, content).await {
+                Ok(()) => fs::rename(&partial_path, &entry_path).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = written {
+                let _ = fs::remove_file(&partial_path).await;
+                return Err(format!("Error writing cache entry: {}", e));
+            }
+
+            Ok(CachedFile {
+                path: body_path,
+                fetched_at: Local::now(),
+                fetch_error: None,
+            })
+        }
+        (Ok(None), Some((_, fetched_at))) => Ok(CachedFile {
+            path: body_path,
+            fetched_at,
+            fetch_error: None,
+        }),
+        (Ok(None), None) => Err(format!(
+            "Unexpected 304 Not Modified response for uncached remote hosts file '{}'",
+            url
+        )),
+        (Err(FetchError::Unreachable(e)), Some((_, fetched_at))) => Ok(CachedFile {
+            path: body_path,
+            fetched_at,
+            fetch_error: Some(e),
+        }),
+        (Err(e), _) => Err(e.into()),
+    }
+}
+
+/// Fetches every URL of `urls` concurrently, with at most `limit` requests in flight.
+/// Results are in the order of `urls`, a failed URL does not stop the others.
+pub async fn fetch_all(
+    app_handle: &AppHandle,
+    urls: &[String],
+    limit: usize,
+) -> Vec<Result<CachedFile, String>> {
+    let semaphore = Arc::new(Semaphore::new(limit.max(1)));
+    let tasks: Vec<_> = urls
+        .iter()
+        .map(|url| {
+            let (app_handle, url, semaphore) = (app_handle.clone(), url.clone(), semaphore.clone());
+            tauri::async_runtime::spawn(async move {
+                let _permit = semaphore.acquire_owned().await;
+                fetch(&app_handle, &url).await
+            })
+        })
+        .collect();
+
+    let mut results = Vec::with_capacity(tasks.len());
+    for (task, url) in tasks.into_iter().zip(urls) {
+        results.push(
+            task.await.unwrap_or_else(|e| {
+                Err(format!("Error fetching remote hosts file '{}': {}", url, e))
+            }),
+        );
+    }
+    results
+}
+
+/// Downloads `url` into the cache, `None` if the server answered that the cached copy is current.
+/// The body is written to a partial file first, so a failed download leaves the cached copy intact.
+async fn download(
+    app_handle: &AppHandle,
+    url: &str,
+    headers: HeaderMap,
+    dir: &Path,
+    key: &str,
+) -> Result<Option<CacheEntry>, FetchError> {
+    let mut response = remote_hosts::fetch_remote_url_with_headers(app_handle, url, headers)
+        .await
+        .map_err(|e| e.map_message(|e| format!("{} '{}'", e, url)))?;
+    if response.status() == StatusCode::NOT_MODIFIED {
+        return Ok(None);
+    }
+
+    let entry = CacheEntry {
+        url: url.to_string(),
+        etag: header_text(response.headers(), ETAG),
+        last_modified: header_text(response.headers(), LAST_MODIFIED),
+        fetched_at: Local::now().to_rfc3339(),
+    };
+
+    fs::create_dir_all(dir)
+        .await
+        .map_err(|e| FetchError::Failed(format!("Error creating cache directory: {}", e)))?;
+    // Unique, as another composition may be downloading the same URL
+    let partial_path = dir.join(format!("{}.hosts.{}.part", key, uuid::Uuid::new_v4()));
+    let mut file = fs::File::create(&partial_path)
+        .await
+        .map_err(|e| FetchError::Failed(format!("Error creating cache file: {}", e)))?;
+    if let Err(e) = remote_hosts::write_response_body(&mut response, &mut file).await {
+        let _ = fs::remove_file(&partial_path).await;
+        return Err(e.map_message(|e| format!("{} from remote hosts file '{}'", e, url)));
+    }
+    if let Err(e) = fs::rename(&partial_path, dir.join(format!("{}.hosts", key))).await {
+        let _ = fs::remove_file(&partial_path).await;
+        return Err(FetchError::Failed(format!(
+            "Error writing cache file: {}",
+            e
+        )));
+    }
+
+    Ok(Some(entry))
+}
+
+/// URLs of the `@remote` includes of `content`, with the variables it defines itself substituted
+fn remote_urls(content: &str) -> Vec<String> {
+    let substitution =
+        variables::substitute(content, &Variables::new(), &DiagnosticSource::Inline, &[]);
+    HostsDocument::parse(&substitution.content)
+        .directives()
+        .filter(|(_, directive)| directive.name.text == "remote")
+        .filter_map(|(_, directive)| remote_hosts::parse_remote_param(directive.param.text).ok())
+        .map(|(url, _)| url.to_string())
+        .collect()
+}
+
+/// Removes the cached copies of remote files that no local hosts file includes anymore.
+/// A URL built from variables of an including file is not recognized, it is downloaded
+/// again the next time it is included.
+pub async fn prune(app_handle: &AppHandle) -> Result<(), String> {
+    let files_dir = app_handle.path().app_data_dir().unwrap().join("files");
+    let mut files = fs::read_dir(&files_dir)
+        .await
+        .map_err(|e| format!("Error reading hosts files: {}", e))?;
+    let mut keys = HashSet::new();
+    while let Some(file) = files.next_entry().await.map_err(|e| e.to_string())? {
+        if file
+            .path()
+            .extension()
+            .is_some_and(|extension| extension == "hosts")
+        {
+            let content = fs::read_to_string(file.path())
+                .await
+                .map_err(|e| format!("Error reading hosts file: {}", e))?;
+            keys.extend(remote_urls(&content).iter().map(|url| cache_key(url)));
+        }
+    }
+
+    let mut entries = match fs::read_dir(get_cache_dir(app_handle)).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(format!("Error reading cache directory: {}", e)),
+    };
+    while let Some(entry) = entries.next_entry().await.map_err(|e| e.to_string())? {
+        let name = entry.file_name().to_string_lossy().to_string();
+        // Partial files belong to downloads still in progress
+        if name.ends_with(".part") {
+            continue;
+        }
+        let key = name.split('.').next().unwrap_or_default();
+        if !keys.contains(key) {
+            fs::remove_file(entry.path())
+                .await
+                .map_err(|e| format!("Error removing cached file '{}': {}", name, e))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_remote_urls_to_keep() {
+        let content = "@define(LISTS=https://lists.test)\n\
+                       @remote(https://a.test/hosts, ip=0.0.0.0)\n\
+                       @remote(${LISTS}/b.txt) # blocklist\n\
+                       @remote(${ELSEWHERE}/c.txt)\n\
+                       @local(other)\n\
+                       # @remote(https://commented.test)";
+        assert_eq!(
+            remote_urls(content),
+            [
+                "https://a.test/hosts",
+                "https://lists.test/b.txt",
+                "${ELSEWHERE}/c.txt"
+            ]
+        );
+    }
+
+    #[test]
+    fn keys_are_stable() {
+        assert_eq!(
+            cache_key("https://a.test/hosts"),
+            cache_key("https://a.test/hosts")
+        );
+        assert_ne!(
+            cache_key("https://a.test/hosts"),
+            cache_key("https://a.test/hosts2")
+        );
+        assert_eq!(cache_key(""), "cbf29ce484222325");
+    }
+}
@@ -1,3 +1,12 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{BufWriter, Write};
+use std::net::IpAddr;
+use std::str::FromStr;
 use std::time::Duration;
 use tauri::{command, Manager};
 use tokio::fs::File;
@@ -5,19 +14,150 @@ use tokio::io::AsyncWriteExt;
 use url::Url;
 
 use crate::files::write_system_hosts_from_file;
+use crate::hosts_document::{HostsDocument, LineKind};
+use crate::hosts_parser::is_ignored_host;
+use crate::list_formats::{self, ConversionReport, ListFormat};
+use crate::temp_file::TempFile;
+
+/// Start of the options following the URL in `@remote(url, ip=0.0.0.0)`.
+/// Options need a space after the comma, so query strings like `?a=1,ip=2` are kept.
+static OPTION_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r",\s+[a-z]+=").unwrap());
+
+/// How a remote hosts file is changed when it is included or fetched
+#[derive(Debug, Clone, Default)]
+pub struct RemoteOptions {
+    /// Address that blocking entries are pointed at instead of their own
+    pub sink_ip: Option<IpAddr>,
+}
+
+/// Why a remote file could not be downloaded
+#[derive(Debug)]
+pub enum FetchError {
+    /// The server could not be reached or did not answer in time, such as when offline
+    Unreachable(String),
+    /// Anything else, including error statuses and responses that are not hosts files
+    Failed(String),
+}
+
+impl FetchError {
+    fn from_request(context: &str, e: reqwest::Error) -> Self {
+        let message = format!("{}: {}", context, e);
+        if e.is_connect() || e.is_timeout() {
+            FetchError::Unreachable(message)
+        } else {
+            FetchError::Failed(message)
+        }
+    }
+
+    /// The same kind of error with its message changed by `f`
+    pub fn map_message(self, f: impl FnOnce(String) -> String) -> Self {
+        match self {
+            FetchError::Unreachable(message) => FetchError::Unreachable(f(message)),
+            FetchError::Failed(message) => FetchError::Failed(f(message)),
+        }
+    }
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FetchError::Unreachable(message) | FetchError::Failed(message) => f.write_str(message),
+        }
+    }
+}
+
+impl From<FetchError> for String {
+    fn from(e: FetchError) -> Self {
+        e.to_string()
+    }
+}
+
+pub fn parse_sink_ip(value: &str) -> Result<IpAddr, String> {
+    IpAddr::from_str(value.trim()).map_err(|_| format!("Invalid sink IP '{}'", value.trim()))
+}
+
+/// Splits the parameter of `@remote(url, ip=0.0.0.0)` into the URL and its options
+pub fn parse_remote_param(param: &str) -> Result<(&str, RemoteOptions), String> {
+    let mut options = RemoteOptions::default();
+    let Some(start) = OPTION_REGEX.find(param).map(|m| m.start()) else {
+        return Ok((param, options));
+    };
+
+    for option in param[start + 1..].split(',') {
+        let Some((key, value)) = option.split_once('=') else {
+            return Err(format!(
+                "Invalid option '{}'. Expected key=value",
+                option.trim()
+            ));
+        };
+        match key.trim() {
+            "ip" => options.sink_ip = Some(parse_sink_ip(value)?),
+            key => return Err(format!("Unknown option '{}'. Expected ip", key)),
+        }
+    }
+
+    Ok((param[..start].trim(), options))
+}
+
+/// Points every blocking entry of `content` at `sink_ip`. Blocking entries are those for a
+/// loopback or unspecified address of the same family; `localhost` lines are left alone.
+pub fn rewrite_sink_ip(content: &str, sink_ip: IpAddr) -> String {
+    let document = HostsDocument::parse(content);
+    let mut result = String::with_capacity(content.len());
+
+    for (index, line) in document.lines.iter().enumerate() {
+        if index > 0 {
+            result.push('\n');
+        }
+
+        let LineKind::Entry(entry) = &line.kind else {
+            result.push_str(line.raw);
+            continue;
+        };
+        let is_blocking = IpAddr::from_str(entry.ip.text).is_ok_and(|ip| {
+            (ip.is_loopback() || ip.is_unspecified()) && ip.is_ipv4() == sink_ip.is_ipv4()
+        }) && !entry
+            .hostnames
+            .iter()
+            .any(|host| is_ignored_host(host.text));
+
+        if is_blocking {
+            result.push_str(&line.raw[..entry.ip.span.start]);
+            result.push_str(&sink_ip.to_string());
+            result.push_str(&line.raw[entry.ip.span.end..]);
+        } else {
+            result.push_str(line.raw);
+        }
+    }
+
+    result
+}
 
 pub async fn fetch_remote_url(
     app_handle: &tauri::AppHandle,
     url: &str,
 ) -> Result<reqwest::Response, String> {
+    Ok(fetch_remote_url_with_headers(app_handle, url, HeaderMap::new()).await?)
+}
+
+/// Fetches `url` with extra request `headers`. A `304 Not Modified` response to a
+/// conditional request is returned as it is, without a body.
+pub async fn fetch_remote_url_with_headers(
+    app_handle: &tauri::AppHandle,
+    url: &str,
+    headers: HeaderMap,
+) -> Result<reqwest::Response, FetchError> {
     if url.is_empty() {
-        return Err("URL is empty".to_string());
+        return Err(FetchError::Failed("URL is empty".to_string()));
     }
 
-    let fetch_url = Url::parse(&url).map_err(|e| format!("Invalid URL: {}", e))?;
+    let fetch_url =
+        Url::parse(url).map_err(|e| FetchError::Failed(format!("Invalid URL: {}", e)))?;
 
     if fetch_url.scheme() != "https" {
-        return Err("Insecure URL: Only HTTPS is allowed.".to_string());
+        return Err(FetchError::Failed(
+            "Insecure URL: Only HTTPS is allowed.".to_string(),
+        ));
     }
 
     let app_version = app_handle.package_info().version.to_string();
@@ -26,16 +166,21 @@ pub async fn fetch_remote_url(
         .user_agent(format!("hedit.app/{}", app_version))
         .timeout(Duration::from_secs(10))
         .build()
-        .map_err(|e| format!("Error building HTTP client: {}", e))?;
+        .map_err(|e| FetchError::Failed(format!("Error building HTTP client: {}", e)))?;
 
     let response = client
         .get(fetch_url)
         .header(reqwest::header::ACCEPT, "text/plain")
+        .headers(headers)
         .send()
         .await
-        .map_err(|e| format!("Error fetching remote hosts file: {}", e))?
+        .map_err(|e| FetchError::from_request("Error fetching remote hosts file", e))?
         .error_for_status() // Errors on non-2xx status codes
-        .map_err(|e| format!("Failed to fetch remote hosts file: {}", e))?;
+        .map_err(|e| FetchError::Failed(format!("Failed to fetch remote hosts file: {}", e)))?;
+
+    if response.status() == StatusCode::NOT_MODIFIED {
+        return Ok(response);
+    }
 
     let content_type = response
         .headers()
@@ -44,55 +189,89 @@ pub async fn fetch_remote_url(
         .unwrap_or_default();
 
     if !content_type.starts_with("text/plain") {
-        return Err(format!(
+        return Err(FetchError::Failed(format!(
             "Invalid Content-Type: expected 'text/plain', got '{}'",
             content_type
-        ));
+        )));
     }
 
     Ok(response)
 }
 
+/// Streams the body of `response` to `file`
+pub async fn write_response_body(
+    response: &mut reqwest::Response,
+    file: &mut File,
+) -> Result<(), FetchError> {
+    let write_error =
+        |e: std::io::Error| FetchError::Failed(format!("Error writing to file: {}", e));
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| FetchError::from_request("Error reading response body", e))?
+    {
+        file.write_all(&chunk).await.map_err(write_error)?;
+    }
+
+    file.flush().await.map_err(write_error)
+}
+
+/// Downloads `url` to the local hosts file `file_name`, converted to hosts syntax
+/// when it is another list format
 pub async fn fetch_remote_url_to_file(
     app_handle: &tauri::AppHandle,
     url: &str,
     file_name: &str,
-) -> Result<(), String> {
+    sink_ip: Option<&str>,
+) -> Result<ConversionReport, String> {
     if url.is_empty() || file_name.is_empty() {
         return Err("URL or file name is empty".to_string());
     }
+    let sink_ip = sink_ip.map(parse_sink_ip).transpose()?;
 
     let mut response = fetch_remote_url(app_handle, url).await?;
 
+    // Downloaded as it is first, the format is detected from the whole body's first lines
+    let (download, file) = TempFile::create("hedit-remote")?;
+    let mut file = File::from_std(file);
+    write_response_body(&mut response, &mut file).await?;
+    let format = ListFormat::detect_file(download.path())?;
+
     let dir = app_handle.path().app_data_dir().unwrap();
-    let file_path = dir.join("files").join(&file_name);
+    let file_path = dir.join("files").join(file_name);
 
-    let mut file = File::create(&file_path)
-        .await
-        .map_err(|e| format!("Error creating file: {}", e))?;
-
-    file.write_all(
-        format!(
-            "# Fetched from: {}\n# Last fetched: {}\n# --------------\n",
-            url,
-            chrono::Local::now()
-        )
-        .as_bytes(),
-    )
-    .await
-    .map_err(|e| format!("Error writing URL to file: {}", e))?;
+    let mut output = BufWriter::new(
+        std::fs::File::create(&file_path).map_err(|e| format!("Error creating file: {}", e))?,
+    );
 
-    while let Some(chunk) = response
-        .chunk()
-        .await
-        .map_err(|e| format!("Error reading response body: {}", e))?
-    {
-        file.write_all(&chunk)
-            .await
-            .map_err(|e| format!("Error writing to file: {}", e))?;
+    let mut header = format!(
+        "# Fetched from: {}\n# Last fetched: {}\n",
+        url,
+        chrono::Local::now()
+    );
+    if format != ListFormat::Hosts {
+        header.push_str(&format!("# Converted from: {} format\n", format.name()));
     }
+    if let Some(sink_ip) = sink_ip {
+        header.push_str(&format!("# Blocking entries pointed at: {}\n", sink_ip));
+    }
+    header.push_str("# --------------\n");
+    output
+        .write_all(header.as_bytes())
+        .map_err(|e| format!("Error writing URL to file: {}", e))?;
+
+    let report = list_formats::convert_file(download.path(), format, |line| {
+        let line = match sink_ip {
+            Some(sink_ip) => Cow::Owned(rewrite_sink_ip(line, sink_ip)),
+            None => Cow::Borrowed(line),
+        };
+        writeln!(output, "{}", line).map_err(|e| format!("Error writing to file: {}", e))
+    })?;
+    output
+        .flush()
+        .map_err(|e| format!("Error writing to file: {}", e))?;
 
-    Ok(())
+    Ok(report)
 }
 
 #[command]
@@ -101,12 +280,48 @@ pub async fn fetch_remote_hosts_file(
     url: String,
     file_name: String,
     is_active: bool,
-) -> Result<(), String> {
-    fetch_remote_url_to_file(&app_handle, &url, &file_name).await?;
+    sink_ip: Option<String>,
+) -> Result<ConversionReport, String> {
+    let report =
+        fetch_remote_url_to_file(&app_handle, &url, &file_name, sink_ip.as_deref()).await?;
 
     if is_active {
         write_system_hosts_from_file(&app_handle, &file_name).await?;
     }
 
-    Ok(())
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_options_from_the_url() {
+        let (url, options) = parse_remote_param("https://a.test/hosts, ip=0.0.0.0").unwrap();
+        assert_eq!(url, "https://a.test/hosts");
+        assert_eq!(options.sink_ip, Some(IpAddr::from([0, 0, 0, 0])));
+
+        let (url, options) = parse_remote_param("https://a.test/hosts").unwrap();
+        assert_eq!(url, "https://a.test/hosts");
+        assert!(options.sink_ip.is_none());
+    }
+
+    #[test]
+    fn keeps_query_strings_in_the_url() {
+        let (url, options) = parse_remote_param("https://a.test/list?a=1,ip=2").unwrap();
+        assert_eq!(url, "https://a.test/list?a=1,ip=2");
+        assert!(options.sink_ip.is_none());
+
+        let (url, options) =
+            parse_remote_param("https://a.test/list?a=1,ip=2, ip=127.0.0.2").unwrap();
+        assert_eq!(url, "https://a.test/list?a=1,ip=2");
+        assert_eq!(options.sink_ip, Some(IpAddr::from([127, 0, 0, 2])));
+    }
+
+    #[test]
+    fn rejects_unknown_options() {
+        assert!(parse_remote_param("https://a.test/hosts, mode=strict").is_err());
+        assert!(parse_remote_param("https://a.test/hosts, ip=nowhere").is_err());
+    }
 }
@@ -8,6 +8,9 @@ pub enum ConfigKey {
     AutoUpdateHostsEnabled,
     AutoUpdateHostsInterval,
     DisableTelemetry,
+    RemoteFetchConcurrency,
+    HostsBackupRetention,
+    ManagedBlockMode,
 }
 
 impl ConfigKey {
@@ -17,6 +20,9 @@ impl ConfigKey {
             ConfigKey::AutoUpdateHostsEnabled => "autoUpdateHostsEnabled",
             ConfigKey::AutoUpdateHostsInterval => "autoUpdateHostsInterval",
             ConfigKey::DisableTelemetry => "disableTelemetry",
+            ConfigKey::RemoteFetchConcurrency => "remoteFetchConcurrency",
+            ConfigKey::HostsBackupRetention => "hostsBackupRetention",
+            ConfigKey::ManagedBlockMode => "managedBlockMode",
         }
     }
 }
@@ -19,6 +19,9 @@ struct HostsFileMetadata {
     is_active: bool,
     #[serde(rename = "isCombo")]
     is_combo: bool,
+    /// Address that blocking entries of a remote file are pointed at
+    #[serde(rename = "sinkIp")]
+    sink_ip: Option<String>,
 }
 
 /// Update all remote hosts files
@@ -89,7 +92,7 @@ async fn update_remote_hosts_files(app: &AppHandle) -> Result<(), Box<dyn std::e
     for file in files_to_sync {
         if let Some(url) = &file.remote_url {
             let file_name = format!("{}.hosts", file.id);
-            match fetch_remote_url_to_file(app, url, &file_name).await {
+            match fetch_remote_url_to_file(app, url, &file_name, file.sink_ip.as_deref()).await {
                 Ok(_) => {
                     println!("Successfully updated remote hosts file: {}", file.name);
                     // Emit event to notify frontend about the update
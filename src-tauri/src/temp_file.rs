@@ -0,0 +1,79 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+
+/// A file in the system temporary directory, removed when dropped
+pub struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    /// Creates a file with a unique name that only the current user can read and write.
+    /// Creation fails if the path already exists, so a file or symlink planted there
+    /// by another user is never written through.
+    pub fn create(prefix: &str) -> Result<(Self, File), String> {
+        let path = std::env::temp_dir().join(format!("{}-{}", prefix, uuid::Uuid::new_v4()));
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+
+        let file = options
+            .open(&path)
+            .map_err(|e| format!("Error creating temporary file: {}", e))?;
+        Ok((TempFile { path }, file))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Reads a file one line at a time without holding all of it in memory
+pub struct LineReader {
+    reader: BufReader<File>,
+    buffer: Vec<u8>,
+    number: usize,
+}
+
+impl LineReader {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let file = File::open(path)
+            .map_err(|e| format!("Error opening file '{}': {}", path.display(), e))?;
+
+        Ok(LineReader {
+            reader: BufReader::new(file),
+            buffer: Vec::new(),
+            number: 0,
+        })
+    }
+
+    /// The next line without its line feed and its 1-based number, `None` at the end of the file
+    pub fn next_line(&mut self) -> Result<Option<(usize, String)>, String> {
+        self.buffer.clear();
+        let read = self
+            .reader
+            .read_until(b'\n', &mut self.buffer)
+            .map_err(|e| format!("Error reading file: {}", e))?;
+        if read == 0 {
+            return Ok(None);
+        }
+
+        if self.buffer.ends_with(b"\n") {
+            self.buffer.pop();
+        }
+        self.number += 1;
+        Ok(Some((
+            self.number,
+            String::from_utf8_lossy(&self.buffer).into_owned(),
+        )))
+    }
+}
@@ -0,0 +1,152 @@
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+use std::collections::{HashMap, HashSet};
+
+use crate::diagnostics::{Diagnostic, DiagnosticCode, DiagnosticSource, IncludeFrame, Severity};
+use crate::hosts_document::{HostsDocument, LineKind, Span};
+
+static REFERENCE_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"\$\{([^}]*)\}").unwrap());
+
+static NAME_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[A-Za-z_][A-Za-z0-9_]*$").unwrap());
+
+/// Variables visible to a file, including those defined by the files including it
+pub type Variables = HashMap<String, String>;
+
+/// Parses the parameter of `@define(NAME=value)`
+pub fn parse_definition(param: &str) -> Option<(&str, &str)> {
+    let (name, value) = param.split_once('=')?;
+    let (name, value) = (name.trim(), value.trim());
+
+    if NAME_REGEX.is_match(name) && !value.is_empty() {
+        Some((name, value))
+    } else {
+        None
+    }
+}
+
+/// Content of a file with its `${NAME}` references replaced
+pub struct Substitution {
+    pub content: String,
+    /// Inherited variables plus the ones defined by the file itself
+    pub variables: Variables,
+    /// Names of the variables referenced by the file
+    pub used: HashSet<String>,
+    /// Undefined references
+    pub diagnostics: Vec<Diagnostic>,
+    /// Warning to report for each variable defined by the file, if it turns out to be unused
+    definitions: Vec<(String, Diagnostic)>,
+}
+
+impl Substitution {
+    /// Variables defined by the file that are not in `used`
+    pub fn unused(&self, used: &HashSet<String>) -> Vec<Diagnostic> {
+        self.definitions
+            .iter()
+            .filter(|(name, _)| !used.contains(name))
+            .map(|(_, diagnostic)| diagnostic.clone())
+            .collect()
+    }
+}
+
+/// Replaces `${NAME}` in entries and directives with the value from `@define(NAME=value)`
+/// in `content` or in `inherited`. Comments are left untouched.
+pub fn substitute(
+    content: &str,
+    inherited: &Variables,
+    source: &DiagnosticSource,
+    included_from: &[IncludeFrame],
+) -> Substitution {
+    let document = HostsDocument::parse(content);
+    let mut variables = inherited.clone();
+    let mut definitions = Vec::new();
+
+    for (line, directive) in document.directives() {
+        if directive.name.text != "define" {
+            continue;
+        }
+        if let Some((name, value)) = parse_definition(directive.param.text) {
+            variables.insert(name.to_string(), value.to_string());
+            definitions.push((
+                name.to_string(),
+                Diagnostic::new(
+                    Severity::Warning,
+                    DiagnosticCode::UnusedVariable,
+                    format!("Variable '{}' is never used", name),
+                    source,
+                    line.raw,
+                    directive.param.span,
+                ),
+            ));
+        }
+    }
+
+    let mut used = HashSet::new();
+    let mut diagnostics = Vec::new();
+    let mut result = String::with_capacity(content.len());
+
+    for (index, line) in document.lines.iter().enumerate() {
+        if index > 0 {
+            result.push('\n');
+        }
+
+        // Only the part before a trailing comment is substituted
+        let end = match &line.kind {
+            LineKind::Entry(entry) => entry.comment.map(|c| c.span.start),
+            LineKind::Directive(directive) if directive.name.text != "define" => {
+                directive.comment.map(|c| c.span.start)
+            }
+            LineKind::MalformedDirective => None,
+            _ => Some(0),
+        }
+        .unwrap_or(line.raw.len());
+
+        let (head, tail) = line.raw.split_at(end);
+        if !head.contains("${") {
+            result.push_str(line.raw);
+            continue;
+        }
+
+        let replaced = REFERENCE_REGEX.replace_all(head, |captures: &Captures| {
+            let name = &captures[1];
+            match variables.get(name) {
+                Some(value) => {
+                    used.insert(name.to_string());
+                    value.clone()
+                }
+                None => {
+                    let reference = captures.get(0).unwrap();
+                    diagnostics.push(Diagnostic::new(
+                        Severity::Error,
+                        DiagnosticCode::UndefinedVariable,
+                        format!("Undefined variable '{}'", name),
+                        source,
+                        line.raw,
+                        Span {
+                            line: line.number,
+                            start: reference.start(),
+                            end: reference.end(),
+                        },
+                    ));
+                    reference.as_str().to_string()
+                }
+            }
+        });
+        result.push_str(&replaced);
+        result.push_str(tail);
+    }
+
+    for (_, diagnostic) in definitions.iter_mut() {
+        diagnostic.included_from = included_from.to_vec();
+    }
+    for diagnostic in diagnostics.iter_mut() {
+        diagnostic.included_from = included_from.to_vec();
+    }
+
+    Substitution {
+        content: result,
+        variables,
+        used,
+        diagnostics,
+        definitions,
+    }
+}
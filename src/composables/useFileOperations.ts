@@ -15,9 +15,14 @@ export function useFileOperations() {
     hostsStore.setSelected(fileId)
   }
 
-  const handleCreateFile = async ({ remote = false, fileName = '', remoteUrl = '' } = {}) => {
+  const handleCreateFile = async ({
+    remote = false,
+    fileName = '',
+    remoteUrl = '',
+    sinkIp = '',
+  } = {}) => {
     const name = fileName || `New ${remote ? 'Remote' : 'Local'} File ${hostsStore.files.length}`
-    const id = await hostsStore.create(name, '', false, remote, remoteUrl)
+    const id = await hostsStore.create(name, '', false, remote, remoteUrl, sinkIp)
     return id
   }
 
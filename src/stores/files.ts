@@ -22,11 +22,42 @@ export interface HostsFile {
   isSelected: boolean
   type: HostsFileType
   remoteUrl?: string | null
+  sinkIp?: string | null
   isCombo: boolean
   content: string
   status: string
 }
 
+export interface ImportedHostsFile {
+  id: string
+  content: string
+  diagnostics: { severity: 'error' | 'warning'; message: string }[]
+}
+
+export interface HostnameChange {
+  hostname: string
+  isIpv6: boolean
+  kind: 'added' | 'removed' | 'repointed'
+  oldIp: string | null
+  newIp: string | null
+}
+
+export interface SystemHostsChange {
+  diff: { changes: HostnameChange[]; unchanged: number }
+  detectedAt: string
+}
+
+/** A hostname that the Hedit block and the rest of /etc/hosts resolve differently */
+export interface BlockConflict {
+  hostname: string
+  isIpv6: boolean
+  systemIp: string
+  systemLine: number
+  heditIp: string
+  heditLine: number
+  winner: 'system' | 'hedit'
+}
+
 const metadataStore = await load('files-metadata.json', { autoSave: 100, defaults: { files: [] } })
 
 export const hostsStore = reactive({
@@ -49,6 +80,7 @@ export const hostsStore = reactive({
     isFirst?: boolean,
     remote?: boolean,
     remoteUrl?: string,
+    sinkIp?: string,
   ): Promise<string> {
     if (remote && !remoteUrl) {
       throw new Error('Remote URL is required for remote hosts file')
@@ -63,6 +95,7 @@ export const hostsStore = reactive({
       isCombo: false,
       type: remote ? HostsFileType.REMOTE : HostsFileType.LOCAL,
       remoteUrl: remote ? remoteUrl : null,
+      sinkIp: remote && sinkIp ? sinkIp : null,
       content,
       status: 'creating',
     }
@@ -98,6 +131,28 @@ export const hostsStore = reactive({
       throw error
     }
   },
+  async importSystemHosts(name: string, isFirst?: boolean): Promise<ImportedHostsFile> {
+    const imported = await invoke<ImportedHostsFile>('import_system_hosts')
+    const file: HostsFile = {
+      id: imported.id,
+      name,
+      isActive: !!isFirst,
+      isSelected: !!isFirst,
+      isCombo: false,
+      type: HostsFileType.LOCAL,
+      remoteUrl: null,
+      content: imported.content,
+      status: 'created',
+    }
+
+    this.files = [...this.files, file]
+    this.saveMetadata()
+    setTimeout(() => {
+      file.status = ''
+    }, 3000)
+
+    return imported
+  },
   async load() {
     const filesData = await metadataStore.get<HostsFile[]>('files')
     if (filesData) {
@@ -132,8 +187,7 @@ export const hostsStore = reactive({
   async init() {
     await this.load()
     if (this.files.length === 0) {
-      const content = await readTextFile('/etc/hosts')
-      await this.create('Original File', content, true)
+      await this.importSystemHosts('Original File', true)
     }
   },
   renameFile(id: string, newName: string) {
@@ -159,6 +213,7 @@ export const hostsStore = reactive({
         url: file.remoteUrl,
         fileName: `${id}.hosts`,
         isActive: file.isActive,
+        sinkIp: file.sinkIp ?? null,
       })
 
       // Reload the content after fetching for the editor to update
@@ -204,6 +259,34 @@ export const hostsStore = reactive({
       throw error
     }
   },
+  async reapplyActiveFile() {
+    const file = this.files.find((file) => file.isActive)
+    if (!file) return
+
+    await invoke('reapply_hosts_file', { fileId: file.id })
+  },
+  async dismissSystemHostsChanges() {
+    await invoke('dismiss_system_hosts_changes')
+  },
+  adoptSystemHostsChanges(changes: HostnameChange[]): HostsFile | undefined {
+    const file = this.files.find((file) => file.isActive)
+    if (!file) return
+
+    // Added at the top, as the first entry for a hostname is the one resolvers use
+    const lines = changes
+      .filter((change) => change.newIp)
+      .map((change) => `${change.newIp} ${change.hostname}`)
+    if (lines.length === 0) return file
+
+    file.content = [
+      `# Adopted from /etc/hosts on ${new Date().toLocaleString()}`,
+      ...lines,
+      '',
+      file.content,
+    ].join('\n')
+    file.status = 'modified'
+    return file
+  },
   async reloadContent(id: string) {
     const file = this.files.find((file) => file.id === id)
     if (!file) return
@@ -215,11 +298,12 @@ export const hostsStore = reactive({
   saveMetadata() {
     return metadataStore.set(
       'files',
-      this.files.map(({ id, name, type, remoteUrl, isActive, isSelected, isCombo }) => ({
+      this.files.map(({ id, name, type, remoteUrl, sinkIp, isActive, isSelected, isCombo }) => ({
         id,
         name,
         type,
         remoteUrl,
+        sinkIp,
         isActive,
         isSelected,
         isCombo,
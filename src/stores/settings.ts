@@ -18,6 +18,9 @@ export const settingsStore = reactive({
   hasCompletedOnboarding: false,
   autoUpdateHostsEnabled: false,
   autoUpdateHostsInterval: 24, // hours
+  remoteFetchConcurrency: 4,
+  hostsBackupRetention: 10,
+  managedBlockMode: false,
   quitOnClose: false,
   autoStart: false,
   async load() {
@@ -31,6 +34,9 @@ export const settingsStore = reactive({
     this.hasCompletedOnboarding = (await store.get<boolean>('hasCompletedOnboarding')) || false
     this.autoUpdateHostsEnabled = (await store.get<boolean>('autoUpdateHostsEnabled')) || false
     this.autoUpdateHostsInterval = (await store.get<number>('autoUpdateHostsInterval')) || 24
+    this.remoteFetchConcurrency = (await store.get<number>('remoteFetchConcurrency')) || 4
+    this.hostsBackupRetention = (await store.get<number>('hostsBackupRetention')) ?? 10
+    this.managedBlockMode = (await store.get<boolean>('managedBlockMode')) || false
     this.quitOnClose = (await store.get<boolean>('quitOnClose')) || false
     this.autoStart = (await store.get<boolean>('autoStart')) || false
   },
@@ -47,6 +53,18 @@ export const settingsStore = reactive({
     this.autoUpdateHostsInterval = interval
     this.save()
   },
+  setRemoteFetchConcurrency(concurrency: number) {
+    this.remoteFetchConcurrency = concurrency
+    this.save()
+  },
+  setHostsBackupRetention(retention: number) {
+    this.hostsBackupRetention = retention
+    this.save()
+  },
+  setManagedBlockMode(managedBlockMode: boolean) {
+    this.managedBlockMode = managedBlockMode
+    this.save()
+  },
   setQuitOnClose(quitOnClose: boolean) {
     this.quitOnClose = quitOnClose
     this.save()
@@ -68,6 +86,9 @@ export const settingsStore = reactive({
     await store.set('hasCompletedOnboarding', this.hasCompletedOnboarding)
     await store.set('autoUpdateHostsEnabled', this.autoUpdateHostsEnabled)
     await store.set('autoUpdateHostsInterval', this.autoUpdateHostsInterval)
+    await store.set('remoteFetchConcurrency', this.remoteFetchConcurrency)
+    await store.set('hostsBackupRetention', this.hostsBackupRetention)
+    await store.set('managedBlockMode', this.managedBlockMode)
     await store.set('quitOnClose', this.quitOnClose)
     await store.set('autoStart', this.autoStart)
     await store.save()